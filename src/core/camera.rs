@@ -0,0 +1,46 @@
+use cgmath::{Deg, Matrix4, Point3, Vector3};
+
+/// Converts cgmath's OpenGL style clip space (y up, z in -1..1) into Vulkan's (y down, z in 0..1).
+#[rustfmt::skip]
+pub const OPENGL_TO_VULKAN: Matrix4<f32> = Matrix4::new(
+    1.0,  0.0, 0.0, 0.0,
+    0.0, -1.0, 0.0, 0.0,
+    0.0,  0.0, 0.5, 0.0,
+    0.0,  0.0, 0.5, 1.0,
+);
+
+pub struct Camera {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    pub fovy: Deg<f32>,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera {
+    pub fn new(eye: Point3<f32>, target: Point3<f32>) -> Self {
+        Self {
+            eye,
+            target,
+            up: Vector3::unit_y(),
+            fovy: Deg(45.0),
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    pub fn projection_matrix(&self, aspect: f32) -> Matrix4<f32> {
+        OPENGL_TO_VULKAN * cgmath::perspective(self.fovy, aspect, self.near, self.far)
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new(Point3::new(1.5, 1.2, 1.5), Point3::new(0.0, 0.5, 0.0))
+    }
+}
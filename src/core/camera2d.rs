@@ -0,0 +1,196 @@
+use ash::vk;
+
+use cgmath::Matrix4;
+
+use crate::core::draw::DrawList;
+use crate::core::recorder::Rect;
+use crate::core::sprite::SpriteBatch;
+
+/// How the design canvas is fitted into a window of a different size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalePolicy {
+    /// Whole canvas visible, keeping its aspect ratio, with bars on the sides or top and bottom.
+    Fit,
+    /// Window fully covered, keeping the aspect ratio, cropping whatever sticks out.
+    Fill,
+    /// Window fully covered, distorting the canvas if the aspect ratios differ.
+    Stretch,
+    /// Like `Fit`, but only whole multiples of the design size, for pixel art.
+    /// Falls back to `Fit` when the window is smaller than the canvas.
+    Integer,
+}
+
+impl ScalePolicy {
+    /// The following policy, wrapping around, to cycle through them at runtime.
+    pub fn next(self) -> Self {
+        match self {
+            ScalePolicy::Fit => ScalePolicy::Fill,
+            ScalePolicy::Fill => ScalePolicy::Stretch,
+            ScalePolicy::Stretch => ScalePolicy::Integer,
+            ScalePolicy::Integer => ScalePolicy::Fit,
+        }
+    }
+}
+
+/// Orthographic camera over a fixed design canvas (e.g. 1920x1080). Game code works in design
+/// coordinates, origin at the top left and y going down, whatever the actual window size is.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera2D {
+    pub design_width: f32,
+    pub design_height: f32,
+    pub policy: ScalePolicy,
+}
+
+impl Camera2D {
+    pub fn new(design_width: f32, design_height: f32, policy: ScalePolicy) -> Self {
+        Self {
+            design_width,
+            design_height,
+            policy,
+        }
+    }
+
+    /// Maps design coordinates to clip space, to be used as the `SpriteBatch` transform.
+    pub fn projection(&self) -> Matrix4<f32> {
+        SpriteBatch::pixel_projection(self.design_width, self.design_height)
+    }
+
+    /// Horizontal and vertical scale from design units to window pixels.
+    pub fn scale(&self, extent: vk::Extent2D) -> (f32, f32) {
+        let scale_x = extent.width as f32 / self.design_width;
+        let scale_y = extent.height as f32 / self.design_height;
+
+        match self.policy {
+            ScalePolicy::Fit => {
+                let scale = scale_x.min(scale_y);
+                (scale, scale)
+            },
+            ScalePolicy::Fill => {
+                let scale = scale_x.max(scale_y);
+                (scale, scale)
+            },
+            ScalePolicy::Stretch => (scale_x, scale_y),
+            ScalePolicy::Integer => {
+                let fit = scale_x.min(scale_y);
+                let scale = match fit >= 1.0 {
+                    true => fit.floor(),
+                    false => fit,
+                };
+                (scale, scale)
+            },
+        }
+    }
+
+    /// The window area, in pixels, covered by the design canvas. Centered in the window,
+    /// so it can start at negative coordinates with `ScalePolicy::Fill`.
+    pub fn viewport(&self, extent: vk::Extent2D) -> Rect {
+        let (scale_x, scale_y) = self.scale(extent);
+        let [x, y] = self.design_to_screen(extent, 0.0, 0.0);
+
+        Rect::new(x, y, self.design_width * scale_x, self.design_height * scale_y)
+    }
+
+    /// Restricts the following draws to the canvas, leaving the bars in the clear color.
+    pub fn apply(&self, extent: vk::Extent2D, draw_list: &mut DrawList) {
+        let viewport = self.viewport(extent);
+        draw_list.set_viewport(viewport);
+        draw_list.set_scissor(viewport);
+    }
+
+    /// Converts a window position, such as the mouse cursor, into design coordinates.
+    /// Returns `None` over the letterbox bars.
+    pub fn screen_to_design(&self, extent: vk::Extent2D, x: f32, y: f32) -> Option<[f32; 2]> {
+        let (scale_x, scale_y) = self.scale(extent);
+        let [offset_x, offset_y] = self.offset(extent);
+        let design_x = (x - offset_x) / scale_x;
+        let design_y = (y - offset_y) / scale_y;
+
+        let inside = (0.0..self.design_width).contains(&design_x)
+            && (0.0..self.design_height).contains(&design_y);
+
+        match inside {
+            true => Some([design_x, design_y]),
+            false => None,
+        }
+    }
+
+    pub fn design_to_screen(&self, extent: vk::Extent2D, x: f32, y: f32) -> [f32; 2] {
+        let (scale_x, scale_y) = self.scale(extent);
+        let [offset_x, offset_y] = self.offset(extent);
+
+        [offset_x + x * scale_x, offset_y + y * scale_y]
+    }
+
+    /// Window position of the canvas' top left corner, rounded to whole pixels.
+    fn offset(&self, extent: vk::Extent2D) -> [f32; 2] {
+        let (scale_x, scale_y) = self.scale(extent);
+
+        [
+            ((extent.width as f32 - self.design_width * scale_x) / 2.0).round(),
+            ((extent.height as f32 - self.design_height * scale_y) / 2.0).round(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICIES: [ScalePolicy; 4] = [ScalePolicy::Fit, ScalePolicy::Fill, ScalePolicy::Stretch, ScalePolicy::Integer];
+
+    fn extent(width: u32, height: u32) -> vk::Extent2D {
+        vk::Extent2D { width, height }
+    }
+
+    #[test]
+    fn screen_to_design_inverts_design_to_screen() {
+        let windows = [extent(1920, 1080), extent(1280, 1024), extent(3000, 1000), extent(640, 360)];
+        let points = [[0.0, 0.0], [960.0, 540.0], [100.5, 1000.25], [1919.0, 1079.0]];
+
+        for policy in POLICIES {
+            let camera = Camera2D::new(1920.0, 1080.0, policy);
+
+            for window in windows {
+                for [x, y] in points {
+                    let [screen_x, screen_y] = camera.design_to_screen(window, x, y);
+                    let [design_x, design_y] = camera.screen_to_design(window, screen_x, screen_y)
+                        .unwrap_or_else(|| panic!("{:?} {:?}: ({}, {}) left the canvas", policy, window, x, y));
+
+                    assert!((design_x - x).abs() < 1e-3 && (design_y - y).abs() < 1e-3,
+                        "{:?} {:?}: ({}, {}) came back as ({}, {})", policy, window, x, y, design_x, design_y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn screen_to_design_is_none_over_the_bars() {
+        let camera = Camera2D::new(1920.0, 1080.0, ScalePolicy::Fit);
+
+        // 1920x1440 leaves 180 pixel bars above and below the canvas
+        assert_eq!(camera.screen_to_design(extent(1920, 1440), 960.0, 100.0), None);
+        assert_eq!(camera.screen_to_design(extent(1920, 1440), 960.0, 180.0), Some([960.0, 0.0]));
+    }
+
+    #[test]
+    fn integer_uses_whole_multiples() {
+        let camera = Camera2D::new(320.0, 180.0, ScalePolicy::Integer);
+
+        assert_eq!(camera.scale(extent(1000, 700)), (3.0, 3.0));
+        assert_eq!(camera.viewport(extent(1000, 700)), Rect::new(20.0, 80.0, 960.0, 540.0));
+    }
+
+    #[test]
+    fn integer_falls_back_to_fit_below_the_design_size() {
+        let camera = Camera2D::new(1920.0, 1080.0, ScalePolicy::Integer);
+        let fit = Camera2D::new(1920.0, 1080.0, ScalePolicy::Fit);
+
+        for window in [extent(960, 540), extent(1280, 1024), extent(800, 600)] {
+            assert_eq!(camera.scale(window), fit.scale(window));
+            assert_eq!(camera.viewport(window), fit.viewport(window));
+        }
+
+        // the whole canvas stays visible
+        assert_eq!(camera.viewport(extent(800, 600)), Rect::new(0.0, 75.0, 800.0, 450.0));
+    }
+}
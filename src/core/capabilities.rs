@@ -0,0 +1,312 @@
+use ash::vk;
+
+use std::fmt;
+
+/// Optional device functionality the renderer can ask for in `DeviceRequirements`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DeviceFeature {
+    SamplerAnisotropy,
+    FillModeNonSolid,
+    WideLines,
+    /// Vulkan 1.2: runtime sized, partially bound and non uniformly indexed sampler arrays.
+    DescriptorIndexing,
+    /// Vulkan 1.2.
+    TimelineSemaphores,
+    /// Vulkan 1.3.
+    DynamicRendering,
+    /// Vulkan 1.3.
+    Synchronization2,
+}
+
+impl DeviceFeature {
+    pub const ALL: [DeviceFeature; 7] = [
+        DeviceFeature::SamplerAnisotropy,
+        DeviceFeature::FillModeNonSolid,
+        DeviceFeature::WideLines,
+        DeviceFeature::DescriptorIndexing,
+        DeviceFeature::TimelineSemaphores,
+        DeviceFeature::DynamicRendering,
+        DeviceFeature::Synchronization2,
+    ];
+
+    /// Lowest device API version that exposes the feature.
+    pub fn api_version(&self) -> u32 {
+        match self {
+            DeviceFeature::SamplerAnisotropy
+            | DeviceFeature::FillModeNonSolid
+            | DeviceFeature::WideLines => vk::API_VERSION_1_0,
+            DeviceFeature::DescriptorIndexing
+            | DeviceFeature::TimelineSemaphores => vk::API_VERSION_1_2,
+            DeviceFeature::DynamicRendering
+            | DeviceFeature::Synchronization2 => vk::API_VERSION_1_3,
+        }
+    }
+}
+
+/// What a device must (`required`) or should (`optional`) support to be picked. Devices missing
+/// a required feature or limit are skipped; optional features are enabled where available and
+/// make a device score higher.
+#[derive(Clone, Debug)]
+pub struct DeviceRequirements {
+    pub api_version: u32,
+    pub required: Vec<DeviceFeature>,
+    pub optional: Vec<DeviceFeature>,
+    /// Smallest acceptable `max_image_dimension2_d`, i.e. the largest texture that must load.
+    pub min_image_dimension: u32,
+    /// Smallest acceptable `max_push_constants_size`, in bytes.
+    pub min_push_constants_size: u32,
+}
+
+impl Default for DeviceRequirements {
+    fn default() -> Self {
+        Self {
+            api_version: vk::API_VERSION_1_0,
+            required: vec![],
+            optional: vec![DeviceFeature::SamplerAnisotropy],
+            // both are the minimums guaranteed by the spec
+            min_image_dimension: 4096,
+            min_push_constants_size: 128,
+        }
+    }
+}
+
+impl DeviceRequirements {
+    /// The optional features `support` lacks are dropped, the required ones make this fail
+    /// with the reason.
+    pub fn negotiate(&self, support: &DeviceCapabilities) -> Result<DeviceCapabilities, String> {
+        if support.api_version < self.api_version {
+            return Err(format!(
+                "Vulkan {} required, device has {}",
+                ApiVersion(self.api_version),
+                ApiVersion(support.api_version),
+            ));
+        }
+
+        if support.limits.max_image_dimension2_d < self.min_image_dimension {
+            return Err(format!("textures only up to {} pixels", support.limits.max_image_dimension2_d));
+        }
+
+        if support.limits.max_push_constants_size < self.min_push_constants_size {
+            return Err(format!("push constants only up to {} bytes", support.limits.max_push_constants_size));
+        }
+
+        if let Some(missing) = self.required.iter().find(|feature| !support.has(**feature)) {
+            return Err(format!("feature {:?} not supported", missing));
+        }
+
+        let mut enabled = self.required.clone();
+        for feature in &self.optional {
+            if support.has(*feature) && !enabled.contains(feature) {
+                enabled.push(*feature);
+            }
+        }
+
+        Ok(DeviceCapabilities {
+            api_version: support.api_version,
+            limits: support.limits,
+            features: enabled,
+        })
+    }
+}
+
+/// Features and limits of a device. Straight from `query` it lists everything the device
+/// supports; `RendererDevice::capabilities` only lists what was actually enabled.
+#[derive(Clone, Debug)]
+pub struct DeviceCapabilities {
+    /// The device's version, capped to the one the instance was created with.
+    pub api_version: u32,
+    pub limits: vk::PhysicalDeviceLimits,
+    pub features: Vec<DeviceFeature>,
+}
+
+impl DeviceCapabilities {
+    /// Version the instance is created with, device features above it are never used.
+    pub const INSTANCE_API_VERSION: u32 = vk::API_VERSION_1_3;
+
+    pub fn query(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let props = unsafe {
+            instance.get_physical_device_properties(physical_device)
+        };
+        let api_version = props.api_version.min(Self::INSTANCE_API_VERSION);
+
+        let mut vulkan12 = vk::PhysicalDeviceVulkan12Features::default();
+        let mut vulkan13 = vk::PhysicalDeviceVulkan13Features::default();
+
+        // the 1.x feature structs may only be chained when the device knows them
+        let core = match api_version >= vk::API_VERSION_1_1 {
+            false => unsafe { instance.get_physical_device_features(physical_device) },
+            true => {
+                let mut features2 = vk::PhysicalDeviceFeatures2::builder();
+                if api_version >= vk::API_VERSION_1_2 {
+                    features2 = features2.push_next(&mut vulkan12);
+                }
+                if api_version >= vk::API_VERSION_1_3 {
+                    features2 = features2.push_next(&mut vulkan13);
+                }
+
+                unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+                features2.features
+            }
+        };
+
+        let supported = |feature: DeviceFeature| match feature {
+            DeviceFeature::SamplerAnisotropy => core.sampler_anisotropy == vk::TRUE,
+            DeviceFeature::FillModeNonSolid => core.fill_mode_non_solid == vk::TRUE,
+            DeviceFeature::WideLines => core.wide_lines == vk::TRUE,
+            DeviceFeature::DescriptorIndexing => {
+                vulkan12.descriptor_indexing == vk::TRUE
+                    && vulkan12.runtime_descriptor_array == vk::TRUE
+                    && vulkan12.descriptor_binding_partially_bound == vk::TRUE
+                    && vulkan12.shader_sampled_image_array_non_uniform_indexing == vk::TRUE
+            },
+            DeviceFeature::TimelineSemaphores => vulkan12.timeline_semaphore == vk::TRUE,
+            DeviceFeature::DynamicRendering => vulkan13.dynamic_rendering == vk::TRUE,
+            DeviceFeature::Synchronization2 => vulkan13.synchronization2 == vk::TRUE,
+        };
+
+        let features = DeviceFeature::ALL.into_iter()
+            .filter(|feature| api_version >= feature.api_version() && supported(*feature))
+            .collect();
+
+        Self {
+            api_version,
+            limits: props.limits,
+            features,
+        }
+    }
+
+    pub fn has(&self, feature: DeviceFeature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /// Highest anisotropy to create samplers with, 1.0 when it is not enabled.
+    pub fn max_anisotropy(&self) -> f32 {
+        match self.has(DeviceFeature::SamplerAnisotropy) {
+            true => self.limits.max_sampler_anisotropy.min(16.0),
+            false => 1.0,
+        }
+    }
+
+    /// Core features to enable, as `vkCreateDevice` expects them.
+    pub fn core_features(&self) -> vk::PhysicalDeviceFeatures {
+        vk::PhysicalDeviceFeatures::builder()
+            .sampler_anisotropy(self.has(DeviceFeature::SamplerAnisotropy))
+            .fill_mode_non_solid(self.has(DeviceFeature::FillModeNonSolid))
+            .wide_lines(self.has(DeviceFeature::WideLines))
+            .build()
+    }
+
+    pub fn vulkan12_features(&self) -> vk::PhysicalDeviceVulkan12Features {
+        let descriptor_indexing = self.has(DeviceFeature::DescriptorIndexing);
+
+        vk::PhysicalDeviceVulkan12Features::builder()
+            .descriptor_indexing(descriptor_indexing)
+            .runtime_descriptor_array(descriptor_indexing)
+            .descriptor_binding_partially_bound(descriptor_indexing)
+            .shader_sampled_image_array_non_uniform_indexing(descriptor_indexing)
+            .timeline_semaphore(self.has(DeviceFeature::TimelineSemaphores))
+            .build()
+    }
+
+    pub fn vulkan13_features(&self) -> vk::PhysicalDeviceVulkan13Features {
+        vk::PhysicalDeviceVulkan13Features::builder()
+            .dynamic_rendering(self.has(DeviceFeature::DynamicRendering))
+            .synchronization2(self.has(DeviceFeature::Synchronization2))
+            .build()
+    }
+}
+
+/// Displays a packed `vk::make_api_version` as `major.minor.patch`.
+pub struct ApiVersion(pub u32);
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}",
+            vk::api_version_major(self.0),
+            vk::api_version_minor(self.0),
+            vk::api_version_patch(self.0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Vulkan 1.2 device with anisotropy and timeline semaphores but no descriptor indexing.
+    fn support() -> DeviceCapabilities {
+        let limits = vk::PhysicalDeviceLimits {
+            max_image_dimension2_d: 16384,
+            max_push_constants_size: 256,
+            max_sampler_anisotropy: 16.0,
+            ..Default::default()
+        };
+
+        DeviceCapabilities {
+            api_version: vk::API_VERSION_1_2,
+            limits,
+            features: vec![DeviceFeature::SamplerAnisotropy, DeviceFeature::TimelineSemaphores],
+        }
+    }
+
+    #[test]
+    fn missing_required_feature_fails() {
+        let requirements = DeviceRequirements {
+            required: vec![DeviceFeature::DescriptorIndexing],
+            ..Default::default()
+        };
+
+        assert!(requirements.negotiate(&support()).is_err());
+    }
+
+    #[test]
+    fn missing_optional_feature_is_dropped() {
+        let requirements = DeviceRequirements {
+            required: vec![DeviceFeature::TimelineSemaphores],
+            optional: vec![DeviceFeature::SamplerAnisotropy, DeviceFeature::DynamicRendering],
+            ..Default::default()
+        };
+
+        let enabled = requirements.negotiate(&support()).unwrap();
+        assert_eq!(enabled.features, vec![DeviceFeature::TimelineSemaphores, DeviceFeature::SamplerAnisotropy]);
+        assert_eq!(enabled.api_version, vk::API_VERSION_1_2);
+        assert_eq!(enabled.max_anisotropy(), 16.0);
+    }
+
+    #[test]
+    fn only_enabled_features_are_turned_on() {
+        let requirements = DeviceRequirements {
+            optional: vec![],
+            ..Default::default()
+        };
+
+        let enabled = requirements.negotiate(&support()).unwrap();
+        assert!(enabled.features.is_empty());
+        assert_eq!(enabled.max_anisotropy(), 1.0);
+        assert_eq!(enabled.core_features().sampler_anisotropy, vk::FALSE);
+        assert_eq!(enabled.vulkan12_features().timeline_semaphore, vk::FALSE);
+    }
+
+    #[test]
+    fn too_old_api_version_or_small_limits_fail() {
+        let newer = DeviceRequirements {
+            api_version: vk::API_VERSION_1_3,
+            ..Default::default()
+        };
+        assert!(newer.negotiate(&support()).is_err());
+
+        let bigger_textures = DeviceRequirements {
+            min_image_dimension: 32768,
+            ..Default::default()
+        };
+        assert!(bigger_textures.negotiate(&support()).is_err());
+
+        let more_push_constants = DeviceRequirements {
+            min_push_constants_size: 512,
+            ..Default::default()
+        };
+        assert!(more_push_constants.negotiate(&support()).is_err());
+    }
+}
@@ -3,30 +3,40 @@ use ash::vk;
 use crate::core::device::RendererDevice;
 
 use anyhow::Result;
+/// One pool per queue role, see `QueueFamilyIndices`. Command buffers must be submitted to
+/// a queue of the family their pool was created for.
 pub struct CommandPools {
     pub graphics: vk::CommandPool,
+    pub transfer: vk::CommandPool,
 }
 
 impl CommandPools {
     pub fn new(
         device: &RendererDevice
     ) -> Result<CommandPools> {
-        let graphics_queue_family = match device.queue_family(vk::QueueFlags::GRAPHICS) {
-            None => panic!("No graphics queue family found, don't know what to do!"),
-            Some(qf) => qf
-        };
+        let families = device.family_indices;
 
-        let graphics_command_pool_info = vk::CommandPoolCreateInfo::builder()
-            .queue_family_index(graphics_queue_family.index)
-            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+        Ok(CommandPools {
+            graphics: Self::create_pool(device, families.graphics, vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)?,
+            // only short lived upload command buffers come from here
+            transfer: Self::create_pool(device, families.transfer, vk::CommandPoolCreateFlags::TRANSIENT)?,
+        })
+    }
 
-        let graphics_command_pool = unsafe {
-            device.logical_device.create_command_pool(&graphics_command_pool_info, None)?
+    fn create_pool(
+        device: &RendererDevice,
+        queue_family_index: u32,
+        flags: vk::CommandPoolCreateFlags,
+    ) -> Result<vk::CommandPool> {
+        let command_pool_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(queue_family_index)
+            .flags(flags);
+
+        let command_pool = unsafe {
+            device.logical_device.create_command_pool(&command_pool_info, None)?
         };
 
-        Ok(CommandPools {
-            graphics: graphics_command_pool,
-        })
+        Ok(command_pool)
     }
 
     pub fn create_command_buffers(
@@ -42,10 +52,11 @@ impl CommandPools {
             device.logical_device.allocate_command_buffers(&command_buffer_allocate_info)
         };
         println!("Command buffers allocated: {:?}", command_buffers);
-        Ok(command_buffers?)
+        command_buffers
     }
 
     pub unsafe fn cleanup(&self, device: &RendererDevice) {
+        device.logical_device.destroy_command_pool(self.transfer, None);
         device.logical_device.destroy_command_pool(self.graphics, None);
     }
 }
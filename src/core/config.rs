@@ -0,0 +1,45 @@
+use crate::core::capabilities::DeviceRequirements;
+use crate::core::swapchain::SwapchainConfig;
+
+/// Environment variable that forces the physical device, overriding `RendererConfig::device`.
+/// A number picks the device at that enumeration index, anything else a device whose name
+/// contains it, ignoring case (e.g. `PENCILMAKE_DEVICE=llvmpipe`).
+pub const DEVICE_ENV: &str = "PENCILMAKE_DEVICE";
+
+/// Which physical device the renderer runs on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// The best scoring suitable device.
+    #[default]
+    Auto,
+    /// The device at this position in `enumerate_physical_devices`.
+    Index(usize),
+    /// The first suitable device whose name contains this, ignoring case.
+    Name(String),
+}
+
+impl DeviceSelector {
+    /// Reads `DEVICE_ENV`, `None` when it is unset or empty.
+    pub fn from_env() -> Option<Self> {
+        let value = std::env::var(DEVICE_ENV).ok()?;
+        let value = value.trim();
+
+        if value.is_empty() {
+            return None;
+        }
+
+        match value.parse::<usize>() {
+            Ok(index) => Some(DeviceSelector::Index(index)),
+            Err(_) => Some(DeviceSelector::Name(value.to_string())),
+        }
+    }
+}
+
+/// Options fixed when the renderer is created.
+#[derive(Clone, Debug, Default)]
+pub struct RendererConfig {
+    pub device: DeviceSelector,
+    pub requirements: DeviceRequirements,
+    /// Initial swapchain settings, the only ones that can still change afterwards.
+    pub swapchain: SwapchainConfig,
+}
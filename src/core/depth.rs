@@ -0,0 +1,87 @@
+use ash::vk;
+
+use gpu_allocator::MemoryLocation;
+
+use crate::core::device::RendererDevice;
+use crate::core::memory::AllocatedImage;
+
+use anyhow::Result;
+
+pub struct DepthBuffer {
+    pub image: AllocatedImage,
+    pub image_view: vk::ImageView,
+}
+
+impl DepthBuffer {
+    /// In order of preference.
+    pub const CANDIDATES: [vk::Format; 3] = [
+        vk::Format::D32_SFLOAT,
+        vk::Format::D32_SFLOAT_S8_UINT,
+        vk::Format::D24_UNORM_S8_UINT,
+    ];
+
+    pub fn find_format(instance: &ash::Instance, device: &RendererDevice) -> Result<vk::Format> {
+        for format in Self::CANDIDATES {
+            let properties = unsafe {
+                instance.get_physical_device_format_properties(device.physical_device, format)
+            };
+
+            if properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT) {
+                return Ok(format);
+            }
+        }
+
+        anyhow::bail!("No supported depth format found")
+    }
+
+    pub fn has_stencil(format: vk::Format) -> bool {
+        format == vk::Format::D32_SFLOAT_S8_UINT || format == vk::Format::D24_UNORM_S8_UINT
+    }
+
+    pub fn new(device: &RendererDevice, format: vk::Format, extent: vk::Extent2D) -> Result<DepthBuffer> {
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let image = AllocatedImage::new(device, "Depth buffer", &image_info, MemoryLocation::GpuOnly)?;
+
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::DEPTH)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+
+        let image_view_info = vk::ImageViewCreateInfo::builder()
+            .image(image.image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(*subresource_range);
+
+        let image_view = unsafe {
+            device.logical_device.create_image_view(&image_view_info, None)?
+        };
+
+        Ok(DepthBuffer {
+            image,
+            image_view,
+        })
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        device.logical_device.destroy_image_view(self.image_view, None);
+        self.image.destroy(device);
+    }
+}
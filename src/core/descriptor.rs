@@ -0,0 +1,203 @@
+use ash::vk;
+
+use crate::core::device::RendererDevice;
+use crate::core::light::LightUniform;
+use crate::core::texture::Texture;
+use crate::core::uniform::{UniformBufferObject, UniformBuffers};
+
+use anyhow::Result;
+
+pub struct RendererDescriptors {
+    pub layout: vk::DescriptorSetLayout,
+    pub pool: vk::DescriptorPool,
+    /// `sets[texture][frame]`: each registered texture gets one set per frame in flight,
+    /// all pointing at that frame's uniform buffer.
+    pub sets: Vec<Vec<vk::DescriptorSet>>,
+}
+
+impl RendererDescriptors {
+    pub const UNIFORM_BINDING: u32 = 0;
+    pub const SAMPLER_BINDING: u32 = 1;
+    pub const LIGHT_BINDING: u32 = 2;
+    pub const MAX_TEXTURES: u32 = 256;
+
+    pub fn new(device: &RendererDevice, uniform_buffers: &UniformBuffers) -> Result<RendererDescriptors> {
+        let layout = Self::create_set_layout(device)?;
+        let pool = Self::create_pool(device, uniform_buffers.buffers.len() as u32)?;
+
+        Ok(RendererDescriptors {
+            layout,
+            pool,
+            sets: vec![],
+        })
+    }
+
+    pub fn set(&self, texture: usize, frame: usize) -> vk::DescriptorSet {
+        self.sets[texture][frame]
+    }
+
+    /// Allocates and writes the sets for a newly registered texture, returning its index.
+    pub fn add_texture(
+        &mut self,
+        device: &RendererDevice,
+        uniform_buffers: &UniformBuffers,
+        texture: &Texture
+    ) -> Result<usize> {
+        if self.sets.len() as u32 >= Self::MAX_TEXTURES {
+            anyhow::bail!("No more than {} textures can be registered", Self::MAX_TEXTURES);
+        }
+
+        let layouts = vec![self.layout; uniform_buffers.buffers.len()];
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.pool)
+            .set_layouts(&layouts);
+
+        let sets = unsafe {
+            device.logical_device.allocate_descriptor_sets(&alloc_info)?
+        };
+
+        let image_infos = [
+            vk::DescriptorImageInfo {
+                sampler: texture.sampler,
+                image_view: texture.image_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }
+        ];
+
+        for (i, set) in sets.iter().enumerate() {
+            let buffer_infos = [
+                vk::DescriptorBufferInfo {
+                    buffer: uniform_buffers.buffers[i].buffer,
+                    offset: 0,
+                    range: std::mem::size_of::<UniformBufferObject>() as vk::DeviceSize,
+                }
+            ];
+
+            let light_infos = [
+                vk::DescriptorBufferInfo {
+                    buffer: uniform_buffers.lights[i].buffer,
+                    offset: 0,
+                    range: std::mem::size_of::<LightUniform>() as vk::DeviceSize,
+                }
+            ];
+
+            let writes = [
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(*set)
+                    .dst_binding(Self::UNIFORM_BINDING)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&buffer_infos)
+                    .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(*set)
+                    .dst_binding(Self::SAMPLER_BINDING)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&image_infos)
+                    .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(*set)
+                    .dst_binding(Self::LIGHT_BINDING)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&light_infos)
+                    .build(),
+            ];
+
+            unsafe { device.logical_device.update_descriptor_sets(&writes, &[]) };
+        }
+
+        self.sets.push(sets);
+        Ok(self.sets.len() - 1)
+    }
+
+    /// A new pool and sets for recreated uniform buffers, sharing the layout that the pipelines
+    /// were built against. Texture indices stay the same. `self` is left untouched, so once the
+    /// new one is in place only its pool has to go, with `cleanup_pool`.
+    pub fn reallocate(
+        &self,
+        device: &RendererDevice,
+        uniform_buffers: &UniformBuffers,
+        textures: &[Texture]
+    ) -> Result<RendererDescriptors> {
+        let mut descriptors = RendererDescriptors {
+            layout: self.layout,
+            pool: Self::create_pool(device, uniform_buffers.buffers.len() as u32)?,
+            sets: vec![],
+        };
+
+        for texture in textures {
+            if let Err(error) = descriptors.add_texture(device, uniform_buffers, texture) {
+                unsafe { descriptors.cleanup_pool(device) };
+                return Err(error);
+            }
+        }
+
+        Ok(descriptors)
+    }
+
+    fn create_pool(device: &RendererDevice, frames_in_flight: u32) -> Result<vk::DescriptorPool> {
+        let count = Self::MAX_TEXTURES * frames_in_flight;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: count * 2,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: count,
+            },
+        ];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets(count);
+
+        let pool = unsafe {
+            device.logical_device.create_descriptor_pool(&pool_info, None)?
+        };
+
+        Ok(pool)
+    }
+
+    fn create_set_layout(device: &RendererDevice) -> Result<vk::DescriptorSetLayout> {
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(Self::UNIFORM_BINDING)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::VERTEX)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(Self::SAMPLER_BINDING)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(Self::LIGHT_BINDING)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+        ];
+
+        let layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&bindings);
+
+        let layout = unsafe {
+            device.logical_device.create_descriptor_set_layout(&layout_info, None)?
+        };
+
+        Ok(layout)
+    }
+
+    /// Frees the pool and with it every set, but keeps the layout.
+    pub unsafe fn cleanup_pool(&self, device: &RendererDevice) {
+        device.logical_device.destroy_descriptor_pool(self.pool, None);
+    }
+
+    pub unsafe fn cleanup(&self, device: &RendererDevice) {
+        device.logical_device.destroy_descriptor_pool(self.pool, None);
+        device.logical_device.destroy_descriptor_set_layout(self.layout, None);
+    }
+}
@@ -1,145 +1,569 @@
 use ash::vk;
 
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme, Allocator, AllocatorCreateDesc};
+use gpu_allocator::{AllocatorDebugSettings, MemoryLocation};
+
+use crate::core::capabilities::{ApiVersion, DeviceCapabilities, DeviceRequirements};
+use crate::core::config::DeviceSelector;
+use crate::core::window::RendererWindow;
+
+use std::ffi::CStr;
+use std::mem::ManuallyDrop;
+use std::sync::Mutex;
+
 use anyhow::Result;
 
-pub struct QueueFamily {
-    pub index: u32,
-    pub flags: vk::QueueFlags,
-    pub queues: Vec<vk::Queue>,
+/// Queue family used for each kind of work. Several roles share a family when the device has
+/// no better one, so always compare indices before assuming ownership transfers are needed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueFamilyIndices {
+    pub graphics: u32,
+    /// `None` on headless renderers.
+    pub present: Option<u32>,
+    /// A transfer only (DMA) family if there is one, falling back to graphics.
+    pub transfer: u32,
+    /// An async compute family without graphics if there is one, falling back to graphics.
+    /// Only counts towards the device score for now, no queue is created for it.
+    pub compute: u32,
+}
+
+impl QueueFamilyIndices {
+    /// Every family a queue is created for, once each.
+    pub fn unique(&self) -> Vec<u32> {
+        let mut indices = vec![self.graphics];
+        for index in [self.present, Some(self.transfer)].into_iter().flatten() {
+            if !indices.contains(&index) {
+                indices.push(index);
+            }
+        }
+
+        indices
+    }
+}
+
+/// A physical device as seen by `pick_physical_device`. `score` is `None` when the device can't
+/// run the renderer, with the reason in `unsuitable`.
+pub struct DeviceCandidate {
+    pub physical_device: vk::PhysicalDevice,
+    pub index: usize,
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub score: Option<u64>,
+    pub unsuitable: Option<String>,
 }
 
 pub struct RendererDevice {
     pub physical_device: vk::PhysicalDevice,
     pub logical_device: ash::Device,
-    pub queue_families: Vec<QueueFamily>,
+    /// Only the features that were enabled, see `DeviceRequirements`.
+    pub capabilities: DeviceCapabilities,
+    pub family_indices: QueueFamilyIndices,
     pub graphics_queue: vk::Queue,
+    /// The graphics queue on headless renderers.
+    pub present_queue: vk::Queue,
+    pub transfer_queue: vk::Queue,
+    pub allocator: ManuallyDrop<Mutex<Allocator>>,
 }
 
 impl RendererDevice {
-    fn used_extensions() -> Vec<*const i8> {
-        vec![
-            ash::extensions::khr::Swapchain::name().as_ptr()
-        ]
+    fn used_extensions(window: Option<&RendererWindow>) -> Vec<&'static CStr> {
+        match window {
+            // headless rendering never presents, so the swapchain extension is not required
+            None => vec![],
+            Some(_) => vec![
+                ash::extensions::khr::Swapchain::name()
+            ]
+        }
     }
 
     pub fn new(
         instance: &ash::Instance,
-        layer_pts: &Vec<*const i8>,
+        window: Option<&RendererWindow>,
+        selector: &DeviceSelector,
+        requirements: &DeviceRequirements,
     ) -> Result<Option<RendererDevice>> {
-        let physical_device = match Self::pick_physical_device(instance)? {
+        let (physical_device, name) = match Self::pick_physical_device(instance, window, selector, requirements)? {
             None => return Ok(None),
-            Some(pd) => pd
+            Some(candidate) => (candidate.physical_device, candidate.name)
         };
 
-        let mut queue_families = Self::pick_queue_families(instance, physical_device);
+        let capabilities = match requirements.negotiate(&DeviceCapabilities::query(instance, physical_device)) {
+            Err(reason) => anyhow::bail!("Device {:?} doesn't meet the requirements: {}", name, reason),
+            Ok(capabilities) => capabilities
+        };
+        println!("Vulkan {}, features: {:?}", ApiVersion(capabilities.api_version), capabilities.features);
 
-        let priorities = [1.0f32];
+        let family_indices = match Self::pick_queue_families(instance, physical_device, window)? {
+            None => anyhow::bail!("Device {:?} lost its graphics or present queue", name),
+            Some(family_indices) => family_indices
+        };
+        println!("Queue families: {:?}", family_indices);
 
-        let mut queue_infos: Vec<vk::DeviceQueueCreateInfo> = Vec::with_capacity(queue_families.len());
+        let priorities = [1.0f32];
 
-        for queue_family in &queue_families {
-            queue_infos.push(
+        let queue_infos: Vec<vk::DeviceQueueCreateInfo> = family_indices.unique().into_iter()
+            .map(|index| {
                 vk::DeviceQueueCreateInfo::builder()
-                    .queue_family_index(queue_family.index)
+                    .queue_family_index(index)
                     .queue_priorities(&priorities)
                     .build()
-            )
-        }
+            })
+            .collect();
+
+        let used_extensions: Vec<*const i8> = Self::used_extensions(window).iter()
+            .map(|extension| extension.as_ptr())
+            .collect();
 
-        let used_extensions = Self::used_extensions();
+        let core_features = capabilities.core_features();
+        let mut vulkan12 = capabilities.vulkan12_features();
+        let mut vulkan13 = capabilities.vulkan13_features();
+        let mut features = vk::PhysicalDeviceFeatures2::builder()
+            .features(core_features);
 
-        let device_create_info = vk::DeviceCreateInfo::builder()
+        // chaining a 1.x feature struct the device doesn't know is invalid, even if it is all false
+        if capabilities.api_version >= vk::API_VERSION_1_2 {
+            features = features.push_next(&mut vulkan12);
+        }
+        if capabilities.api_version >= vk::API_VERSION_1_3 {
+            features = features.push_next(&mut vulkan13);
+        }
+
+        let mut device_create_info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(&queue_infos)
-            .enabled_extension_names(&used_extensions)
-            .enabled_layer_names(layer_pts);
+            .enabled_extension_names(&used_extensions);
+
+        // Vulkan 1.0 has no `PhysicalDeviceFeatures2`, only the core features
+        device_create_info = match capabilities.api_version >= vk::API_VERSION_1_1 {
+            true => device_create_info.push_next(&mut features),
+            false => device_create_info.enabled_features(&core_features),
+        };
 
         let device = unsafe {
             instance.create_device(physical_device, &device_create_info, None)?
         };
 
-        let graphics_queue = unsafe {
-            device.get_device_queue(queue_families[0].index, 0)
-        };
+        // one queue per family, shared by the roles picking the same family
+        let queue = |index: u32| unsafe { device.get_device_queue(index, 0) };
+        let graphics_queue = queue(family_indices.graphics);
+        let present_queue = queue(family_indices.present.unwrap_or(family_indices.graphics));
+        let transfer_queue = queue(family_indices.transfer);
 
-        for queue_family in &mut queue_families {
-            unsafe {
-                queue_family.queues.push(device.get_device_queue(queue_family.index, 0));
-            };
-        }
+        let allocator = Allocator::new(&AllocatorCreateDesc {
+            instance: instance.clone(),
+            device: device.clone(),
+            physical_device,
+            debug_settings: AllocatorDebugSettings::default(),
+            buffer_device_address: false,
+        })?;
 
         Ok(Some(RendererDevice {
             physical_device,
             logical_device: device,
-            queue_families,
-            graphics_queue: graphics_queue
+            capabilities,
+            family_indices,
+            graphics_queue,
+            present_queue,
+            transfer_queue,
+            allocator: ManuallyDrop::new(Mutex::new(allocator)),
         }))
     }
 
-    pub fn queue_family(&self, flags: vk::QueueFlags) -> Option<&QueueFamily> {
-        for queue_family in &self.queue_families {
-            if queue_family.flags == flags {
-                return Some(queue_family)
-            }
+    pub fn allocate(
+        &self,
+        name: &str,
+        requirements: vk::MemoryRequirements,
+        location: MemoryLocation,
+        linear: bool,
+    ) -> Result<Allocation> {
+        let allocation = self.allocator.lock().unwrap().allocate(&AllocationCreateDesc {
+            name,
+            requirements,
+            location,
+            linear,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        })?;
+
+        Ok(allocation)
+    }
+
+    pub fn free(&self, allocation: Allocation) {
+        if let Err(error) = self.allocator.lock().unwrap().free(allocation) {
+            println!("Falha ao liberar memória: {:?}", error);
         }
+    }
 
-        None
+    /// Human readable breakdown of every live allocation, largest first.
+    pub fn memory_report(&self) -> String {
+        format!("{:?}", self.allocator.lock().unwrap())
     }
 
-    fn pick_physical_device(
-        instance: &ash::Instance
-    ) -> Result<Option<vk::PhysicalDevice>>  {
+    /// Whether uploads run on another family than rendering, needing ownership transfers.
+    pub fn has_separate_transfer(&self) -> bool {
+        self.family_indices.transfer != self.family_indices.graphics
+    }
+
+    /// Every physical device with its score, in enumeration order.
+    pub fn candidates(
+        instance: &ash::Instance,
+        window: Option<&RendererWindow>,
+        requirements: &DeviceRequirements,
+    ) -> Result<Vec<DeviceCandidate>> {
         let physical_devices = unsafe {
             instance.enumerate_physical_devices()?
         };
 
-        let mut chosen = None;
-
-        for physical_device in physical_devices {
-            let props: vk::PhysicalDeviceProperties = unsafe {
+        let mut candidates = Vec::with_capacity(physical_devices.len());
+        for (index, physical_device) in physical_devices.into_iter().enumerate() {
+            let props = unsafe {
                 instance.get_physical_device_properties(physical_device)
             };
-            
-            if props.device_type == vk::PhysicalDeviceType::DISCRETE_GPU || props.device_type == vk::PhysicalDeviceType::INTEGRATED_GPU {
-                chosen = Some(physical_device);
+            let name = unsafe {
+                CStr::from_ptr(props.device_name.as_ptr()).to_string_lossy().into_owned()
+            };
+
+            let (score, unsuitable) = match Self::score_device(instance, physical_device, &props, window, requirements)? {
+                Ok(score) => (Some(score), None),
+                Err(reason) => (None, Some(reason)),
+            };
+
+            candidates.push(DeviceCandidate {
+                physical_device,
+                index,
+                name,
+                device_type: props.device_type,
+                score,
+                unsuitable,
+            });
+        }
+
+        Ok(candidates)
+    }
+
+    /// Ranks a device by type first, then by its queues, optional features and device local
+    /// memory. CPU and virtual devices still get a score, so software implementations work as
+    /// a fallback. The inner `Err` says why the device can't be used at all.
+    fn score_device(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        props: &vk::PhysicalDeviceProperties,
+        window: Option<&RendererWindow>,
+        requirements: &DeviceRequirements,
+    ) -> Result<Result<u64, String>> {
+        let available_extensions = unsafe {
+            instance.enumerate_device_extension_properties(physical_device)?
+        };
+
+        for required in Self::used_extensions(window) {
+            let supported = available_extensions.iter().any(|extension| unsafe {
+                CStr::from_ptr(extension.extension_name.as_ptr()) == required
+            });
+
+            if !supported {
+                return Ok(Err(format!("extension {:?} not supported", required)));
+            }
+        }
+
+        let capabilities = match requirements.negotiate(&DeviceCapabilities::query(instance, physical_device)) {
+            Err(reason) => return Ok(Err(reason)),
+            Ok(capabilities) => capabilities
+        };
+
+        let family_indices = match Self::pick_queue_families(instance, physical_device, window)? {
+            None => return Ok(Err("no graphics or present queue".to_string())),
+            Some(family_indices) => family_indices
+        };
+
+        if let Some(window) = window {
+            if window.formats(physical_device)?.is_empty() {
+                return Ok(Err("no format for the window surface".to_string()));
+            }
+        }
+
+        let optional_enabled = requirements.optional.iter()
+            .filter(|feature| capabilities.has(**feature))
+            .count();
+
+        let memory = unsafe {
+            instance.get_physical_device_memory_properties(physical_device)
+        };
+        let device_local: u64 = memory.memory_heaps[..memory.memory_heap_count as usize].iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum();
+
+        Ok(Ok(Self::score(props.device_type, &family_indices, optional_enabled, device_local)))
+    }
 
-                break;
+    /// The score of a suitable device, see `score_device`.
+    fn score(
+        device_type: vk::PhysicalDeviceType,
+        family_indices: &QueueFamilyIndices,
+        optional_enabled: usize,
+        device_local: vk::DeviceSize,
+    ) -> u64 {
+        let mut score: u64 = match device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => 10_000,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => 5_000,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => 1_000,
+            vk::PhysicalDeviceType::CPU => 100,
+            _ => 0,
+        };
+
+        // families without graphics let uploads and compute run beside rendering
+        if family_indices.transfer != family_indices.graphics {
+            score += 200;
+        }
+        if family_indices.compute != family_indices.graphics {
+            score += 200;
+        }
+
+        score += optional_enabled as u64 * 100;
+
+        // one point per 256 MiB, capped so memory never outranks the device type
+        score += (device_local >> 28).min(999);
+
+        score
+    }
+
+    /// Picks the device `selector` asks for, overridden by the `PENCILMAKE_DEVICE` environment
+    /// variable when set. Returns `None` when no device can run the renderer, and an error when
+    /// the forced device doesn't exist or is unsuitable.
+    fn pick_physical_device(
+        instance: &ash::Instance,
+        window: Option<&RendererWindow>,
+        selector: &DeviceSelector,
+        requirements: &DeviceRequirements,
+    ) -> Result<Option<DeviceCandidate>>  {
+        let candidates = Self::candidates(instance, window, requirements)?;
+
+        println!("Devices:");
+        for candidate in &candidates {
+            match (&candidate.score, &candidate.unsuitable) {
+                (Some(score), _) => println!("  [{}] {} ({:?}): {}", candidate.index, candidate.name, candidate.device_type, score),
+                (None, reason) => println!("  [{}] {} ({:?}): unsuitable, {}", candidate.index, candidate.name, candidate.device_type, reason.as_deref().unwrap_or_default()),
             }
         }
 
+        let selector = DeviceSelector::from_env().unwrap_or_else(|| selector.clone());
+        let chosen = Self::choose(candidates, &selector)?;
+
+        if let Some(candidate) = &chosen {
+            println!("Using {} ({:?})", candidate.name, selector);
+        }
+
         Ok(chosen)
     }
 
+    /// The candidate `selector` asks for. Returns `None` when no device is suitable, and an error
+    /// when the forced device doesn't exist or is unsuitable.
+    fn choose(candidates: Vec<DeviceCandidate>, selector: &DeviceSelector) -> Result<Option<DeviceCandidate>> {
+        let chosen = match selector {
+            DeviceSelector::Auto => {
+                candidates.into_iter()
+                    .filter(|candidate| candidate.score.is_some())
+                    // the first listed wins ties
+                    .rev()
+                    .max_by_key(|candidate| candidate.score)
+            },
+            DeviceSelector::Index(index) => {
+                match candidates.into_iter().find(|candidate| candidate.index == *index) {
+                    None => anyhow::bail!("No physical device with index {}", index),
+                    Some(candidate) => Some(candidate)
+                }
+            },
+            DeviceSelector::Name(name) => {
+                let wanted = name.to_lowercase();
+                let mut matching = candidates.into_iter()
+                    .filter(|candidate| candidate.name.to_lowercase().contains(&wanted))
+                    .peekable();
+
+                if matching.peek().is_none() {
+                    anyhow::bail!("No physical device named {:?}", name);
+                }
+
+                // prefer a suitable match, otherwise report why the first one can't be used
+                let (suitable, unsuitable): (Vec<_>, Vec<_>) = matching.partition(|candidate| candidate.score.is_some());
+                suitable.into_iter().next().or_else(|| unsuitable.into_iter().next())
+            },
+        };
+
+        if let Some(candidate) = &chosen {
+            if let Some(reason) = &candidate.unsuitable {
+                anyhow::bail!("Physical device {:?} can't be used: {}", candidate.name, reason);
+            }
+        }
+
+        Ok(chosen)
+    }
+
+    /// Picks a family for each role. Graphics prefers a family that can also present, so most
+    /// devices need no sharing between them. Returns `None` without a graphics family, or, with
+    /// a window, without any family that can present to its surface.
     fn pick_queue_families(
         instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice
-    ) -> Vec<QueueFamily> {
+        physical_device: vk::PhysicalDevice,
+        window: Option<&RendererWindow>,
+    ) -> Result<Option<QueueFamilyIndices>> {
         let queue_family_props = unsafe {
             instance.get_physical_device_queue_family_properties(physical_device)
         };
 
-        let mut queue_families: Vec<QueueFamily> = Vec::new();
-
-        for (i, queue_family) in queue_family_props.iter().enumerate() {
-            if queue_family.queue_count <= 0 {
-                continue;
+        let mut present_support = vec![false; queue_family_props.len()];
+        if let Some(window) = window {
+            for (index, supported) in present_support.iter_mut().enumerate() {
+                *supported = unsafe {
+                    window.surface_loader.get_physical_device_surface_support(physical_device, index as u32, window.surface)?
+                };
             }
+        }
 
-            if queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                queue_families.push(QueueFamily {
-                    index: i as u32,
-                    flags: vk::QueueFlags::GRAPHICS,
-                    queues: vec![],
-                });
+        let families = || queue_family_props.iter()
+            .enumerate()
+            .filter(|(_, family)| family.queue_count > 0)
+            .map(|(index, family)| (index as u32, family.queue_flags));
 
-                break;
-            }
+        let first = |matches: &dyn Fn(u32, vk::QueueFlags) -> bool| families()
+            .find(|&(index, flags)| matches(index, flags))
+            .map(|(index, _)| index);
+
+        let graphics_can_present = first(&|index, flags| {
+            flags.contains(vk::QueueFlags::GRAPHICS) && present_support[index as usize]
+        });
+
+        let graphics = match graphics_can_present.or_else(|| first(&|_, flags| flags.contains(vk::QueueFlags::GRAPHICS))) {
+            None => return Ok(None),
+            Some(graphics) => graphics
+        };
+
+        let present = match window {
+            None => None,
+            Some(_) if present_support[graphics as usize] => Some(graphics),
+            Some(_) => match first(&|index, _| present_support[index as usize]) {
+                None => return Ok(None),
+                Some(present) => Some(present)
+            },
+        };
+
+        // graphics and compute families implicitly support transfers
+        let transfer = first(&|_, flags| {
+            flags.contains(vk::QueueFlags::TRANSFER)
+                && !flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        })
+            .or_else(|| first(&|_, flags| {
+                flags.intersects(vk::QueueFlags::TRANSFER | vk::QueueFlags::COMPUTE)
+                    && !flags.contains(vk::QueueFlags::GRAPHICS)
+            }))
+            .unwrap_or(graphics);
+
+        let compute = first(&|_, flags| {
+            flags.contains(vk::QueueFlags::COMPUTE) && !flags.contains(vk::QueueFlags::GRAPHICS)
+        })
+            .unwrap_or(graphics);
+
+        Ok(Some(QueueFamilyIndices {
+            graphics,
+            present,
+            transfer,
+            compute,
+        }))
+    }
+
+    pub unsafe fn cleanup(&mut self) {
+        // the allocator frees its memory blocks on drop, so it must go before the device
+        ManuallyDrop::drop(&mut self.allocator);
+        self.logical_device.destroy_device(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(index: usize, name: &str, device_type: vk::PhysicalDeviceType, score: Option<u64>) -> DeviceCandidate {
+        DeviceCandidate {
+            physical_device: vk::PhysicalDevice::null(),
+            index,
+            name: name.to_string(),
+            device_type,
+            score,
+            unsuitable: match score {
+                None => Some("no graphics queue".to_string()),
+                Some(_) => None,
+            },
         }
+    }
 
-        queue_families
+    /// An integrated GPU, a discrete GPU and lavapipe, scored like `score_device` would.
+    fn candidates() -> Vec<DeviceCandidate> {
+        let shared = QueueFamilyIndices { graphics: 0, present: None, transfer: 0, compute: 0 };
+        let dedicated = QueueFamilyIndices { graphics: 0, present: None, transfer: 1, compute: 2 };
+
+        vec![
+            candidate(0, "Intel(R) UHD Graphics", vk::PhysicalDeviceType::INTEGRATED_GPU,
+                Some(RendererDevice::score(vk::PhysicalDeviceType::INTEGRATED_GPU, &dedicated, 1, 2 << 30))),
+            candidate(1, "NVIDIA GeForce RTX 3060", vk::PhysicalDeviceType::DISCRETE_GPU,
+                Some(RendererDevice::score(vk::PhysicalDeviceType::DISCRETE_GPU, &shared, 0, 12 << 30))),
+            candidate(2, "llvmpipe (LLVM 15.0.7, 256 bits)", vk::PhysicalDeviceType::CPU,
+                Some(RendererDevice::score(vk::PhysicalDeviceType::CPU, &dedicated, 1, 16 << 30))),
+        ]
     }
 
+    fn chosen(candidates: Vec<DeviceCandidate>, selector: DeviceSelector) -> Option<usize> {
+        RendererDevice::choose(candidates, &selector).unwrap().map(|candidate| candidate.index)
+    }
 
-    pub unsafe fn cleanup(&self) {
-        self.logical_device.destroy_device(None);
+    #[test]
+    fn device_type_outranks_queues_features_and_memory() {
+        let shared = QueueFamilyIndices { graphics: 0, present: None, transfer: 0, compute: 0 };
+        let dedicated = QueueFamilyIndices { graphics: 0, present: None, transfer: 1, compute: 2 };
+        let huge = u64::MAX;
+
+        let discrete = RendererDevice::score(vk::PhysicalDeviceType::DISCRETE_GPU, &shared, 0, 0);
+        let integrated = RendererDevice::score(vk::PhysicalDeviceType::INTEGRATED_GPU, &dedicated, 7, huge);
+        let cpu = RendererDevice::score(vk::PhysicalDeviceType::CPU, &dedicated, 7, huge);
+
+        assert!(discrete > integrated);
+        assert!(integrated > cpu);
+        assert!(cpu > 0);
+    }
+
+    #[test]
+    fn auto_picks_the_discrete_gpu() {
+        assert_eq!(chosen(candidates(), DeviceSelector::Auto), Some(1));
+    }
+
+    #[test]
+    fn auto_prefers_the_first_listed_on_ties() {
+        let mut candidates = candidates();
+        candidates[0].score = candidates[1].score;
+
+        assert_eq!(chosen(candidates, DeviceSelector::Auto), Some(0));
+    }
+
+    #[test]
+    fn auto_falls_back_to_cpu_devices_and_skips_unsuitable_ones() {
+        let mut candidates = candidates();
+        candidates[0].score = None;
+        candidates[1].score = None;
+
+        assert_eq!(chosen(candidates, DeviceSelector::Auto), Some(2));
+        assert_eq!(chosen(vec![], DeviceSelector::Auto), None);
+    }
+
+    #[test]
+    fn forced_index_or_name_overrides_the_score() {
+        assert_eq!(chosen(candidates(), DeviceSelector::Index(0)), Some(0));
+        assert_eq!(chosen(candidates(), DeviceSelector::Name("LLVMPIPE".to_string())), Some(2));
+        assert_eq!(chosen(candidates(), DeviceSelector::Name("intel".to_string())), Some(0));
+    }
+
+    #[test]
+    fn forcing_a_missing_or_unsuitable_device_fails() {
+        assert!(RendererDevice::choose(candidates(), &DeviceSelector::Index(3)).is_err());
+        assert!(RendererDevice::choose(candidates(), &DeviceSelector::Name("radeon".to_string())).is_err());
+
+        let mut candidates = candidates();
+        candidates[2] = candidate(2, "llvmpipe", vk::PhysicalDeviceType::CPU, None);
+        assert!(RendererDevice::choose(candidates, &DeviceSelector::Index(2)).is_err());
     }
 }
@@ -0,0 +1,151 @@
+use ash::vk;
+
+use cgmath::Matrix4;
+
+use crate::core::object::vertex::Vertex;
+use crate::core::pipeline::BlendMode;
+use crate::core::recorder::Rect;
+use crate::core::rendermodel::RenderModel;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MeshHandle(pub usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureHandle(pub usize);
+
+impl TextureHandle {
+    /// The 1x1 white texture every renderer starts with.
+    pub const WHITE: TextureHandle = TextureHandle(0);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineHandle(pub usize);
+
+impl PipelineHandle {
+    /// The default 3D mesh pipeline, bound at the start of every frame.
+    pub const DEFAULT: PipelineHandle = PipelineHandle(0);
+    /// Like `DEFAULT`, shaded by the renderer's directional and ambient light.
+    pub const LIT: PipelineHandle = PipelineHandle(1);
+    /// Alpha blended, depth-less pipeline used by `SpriteBatch`.
+    pub const SPRITE: PipelineHandle = PipelineHandle::sprite(BlendMode::Alpha);
+
+    /// The sprite pipeline blending with `blend_mode`, one is built for each of `BlendMode::ALL`.
+    pub const fn sprite(blend_mode: BlendMode) -> PipelineHandle {
+        PipelineHandle(2 + blend_mode as usize)
+    }
+}
+
+/// Per-draw data, mirrors `PushConstants` in default.vert and default.frag.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PushConstants {
+    pub model: Matrix4<f32>,
+    /// Multiplies the fragment color, e.g. a material's diffuse color and alpha.
+    pub color: [f32; 4],
+}
+
+impl PushConstants {
+    pub fn range() -> vk::PushConstantRange {
+        vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<PushConstants>() as u32,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum DrawCommand {
+    SetPipeline(PipelineHandle),
+    SetViewport(Rect),
+    SetScissor(Rect),
+    /// Back to a viewport and scissor covering the whole target.
+    ResetViewport,
+    DrawMesh {
+        mesh: MeshHandle,
+        texture: TextureHandle,
+        transform: Matrix4<f32>,
+        color: [f32; 4],
+    },
+    /// A range of `DrawList::sprite_vertices`, drawn without indices.
+    DrawSprites {
+        texture: TextureHandle,
+        first_vertex: u32,
+        vertex_count: u32,
+        transform: Matrix4<f32>,
+        /// Multiplies the vertex colors, carries the sprites' tint alpha.
+        color: [f32; 4],
+    },
+}
+
+/// Everything to draw in one frame, in submission order. The game fills a new list
+/// (or clears and refills the same one) every frame and hands it to `draw_frame`.
+#[derive(Clone, Debug)]
+pub struct DrawList {
+    pub clear_color: [f32; 4],
+    pub commands: Vec<DrawCommand>,
+    /// Filled by `SpriteBatch::flush` and uploaded to a per-frame vertex buffer by the renderer.
+    pub sprite_vertices: Vec<Vertex>,
+}
+
+impl DrawList {
+    pub fn new() -> Self {
+        Self {
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            commands: vec![],
+            sprite_vertices: vec![],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+        self.sprite_vertices.clear();
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: PipelineHandle) {
+        self.commands.push(DrawCommand::SetPipeline(pipeline));
+    }
+
+    pub fn set_viewport(&mut self, rect: Rect) {
+        self.commands.push(DrawCommand::SetViewport(rect));
+    }
+
+    pub fn set_scissor(&mut self, rect: Rect) {
+        self.commands.push(DrawCommand::SetScissor(rect));
+    }
+
+    pub fn reset_viewport(&mut self) {
+        self.commands.push(DrawCommand::ResetViewport);
+    }
+
+    pub fn draw_mesh(&mut self, mesh: MeshHandle, texture: TextureHandle, transform: Matrix4<f32>) {
+        self.draw_mesh_colored(mesh, texture, transform, [1.0, 1.0, 1.0, 1.0]);
+    }
+
+    pub fn draw_mesh_colored(&mut self, mesh: MeshHandle, texture: TextureHandle, transform: Matrix4<f32>, color: [f32; 4]) {
+        self.commands.push(DrawCommand::DrawMesh {
+            mesh,
+            texture,
+            transform,
+            color,
+        });
+    }
+
+    /// One draw per submesh, with the texture and color of its material.
+    pub fn draw_model(&mut self, model: &RenderModel, transform: Matrix4<f32>) {
+        for part in &model.parts {
+            let material = part.material.map(|index| &model.materials[index]);
+
+            match material {
+                None => self.draw_mesh(part.mesh, TextureHandle::WHITE, transform),
+                Some(material) => self.draw_mesh_colored(part.mesh, material.texture, transform, material.color),
+            }
+        }
+    }
+}
+
+impl Default for DrawList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,134 @@
+use ash::vk;
+
+use crate::core::commandpool::CommandPools;
+use crate::core::device::RendererDevice;
+
+use anyhow::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameResult {
+    /// The frame was submitted and presented, or finished rendering on a headless renderer.
+    Presented,
+    /// The swapchain no longer matches the surface and will be rebuilt before the next frame.
+    OutOfDate,
+    /// Nothing was drawn because the window is minimized.
+    Skipped,
+}
+
+pub struct FrameSync {
+    pub command_buffer: vk::CommandBuffer,
+    pub image_available: vk::Semaphore,
+    pub rendering_finished: vk::Semaphore,
+    pub in_flight: vk::Fence,
+}
+
+/// Command buffers and synchronization for the frames the CPU may record ahead of the GPU.
+/// The number of frames in flight is independent from the swapchain image count;
+/// `images_in_flight` remembers which frame fence last rendered to each image.
+pub struct FrameContext {
+    pub frames: Vec<FrameSync>,
+    pub images_in_flight: Vec<vk::Fence>,
+    pub current_frame: usize,
+}
+
+impl FrameContext {
+    pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+
+    pub fn new(
+        device: &RendererDevice,
+        command_pool: vk::CommandPool,
+        frames_in_flight: usize,
+        image_count: usize
+    ) -> Result<FrameContext> {
+        if frames_in_flight == 0 {
+            anyhow::bail!("At least one frame in flight is required");
+        }
+
+        let command_buffers = CommandPools::create_command_buffers(device, command_pool, frames_in_flight as u32)?;
+
+        let semaphore_info = vk::SemaphoreCreateInfo::builder();
+
+        let fence_info = vk::FenceCreateInfo::builder()
+            .flags(vk::FenceCreateFlags::SIGNALED);
+
+        let mut frames = Vec::with_capacity(frames_in_flight);
+
+        for command_buffer in command_buffers {
+            let frame = unsafe {
+                FrameSync {
+                    command_buffer,
+                    image_available: device.logical_device.create_semaphore(&semaphore_info, None)?,
+                    rendering_finished: device.logical_device.create_semaphore(&semaphore_info, None)?,
+                    in_flight: device.logical_device.create_fence(&fence_info, None)?,
+                }
+            };
+
+            frames.push(frame);
+        }
+
+        Ok(FrameContext {
+            frames,
+            images_in_flight: vec![vk::Fence::null(); image_count],
+            current_frame: 0,
+        })
+    }
+
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn current(&self) -> &FrameSync {
+        &self.frames[self.current_frame]
+    }
+
+    /// Blocks until the GPU is done with the work last submitted for the current frame slot.
+    pub fn wait_current(&self, device: &RendererDevice) -> Result<()> {
+        let fences = [self.current().in_flight];
+
+        unsafe {
+            device.logical_device.wait_for_fences(&fences, true, u64::MAX)?;
+        }
+
+        Ok(())
+    }
+
+    /// Waits for whichever frame used `image_index` last and hands the image over to the current frame.
+    pub fn claim_image(&mut self, device: &RendererDevice, image_index: u32) -> Result<()> {
+        let image_fence = self.images_in_flight[image_index as usize];
+        let current_fence = self.current().in_flight;
+
+        if image_fence != vk::Fence::null() && image_fence != current_fence {
+            unsafe {
+                device.logical_device.wait_for_fences(&[image_fence], true, u64::MAX)?;
+            }
+        }
+
+        self.images_in_flight[image_index as usize] = current_fence;
+
+        unsafe {
+            device.logical_device.reset_fences(&[current_fence])?;
+        }
+
+        Ok(())
+    }
+
+    pub fn advance(&mut self) {
+        self.current_frame = (self.current_frame + 1) % self.frames.len();
+    }
+
+    /// Forgets the image ownership, to be called after the swapchain images were replaced.
+    pub fn reset_images(&mut self, image_count: usize) {
+        self.images_in_flight = vec![vk::Fence::null(); image_count];
+    }
+
+    pub unsafe fn cleanup(&self, device: &RendererDevice, command_pool: vk::CommandPool) {
+        let command_buffers: Vec<_> = self.frames.iter().map(|frame| frame.command_buffer).collect();
+        device.logical_device.free_command_buffers(command_pool, &command_buffers);
+
+        for frame in &self.frames {
+            device.logical_device.destroy_semaphore(frame.image_available, None);
+            device.logical_device.destroy_semaphore(frame.rendering_finished, None);
+            device.logical_device.destroy_fence(frame.in_flight, None);
+        }
+    }
+}
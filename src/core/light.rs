@@ -0,0 +1,52 @@
+use cgmath::{InnerSpace, Vector3};
+
+/// Mirrors `Light` at binding 2 of lit.frag. Every member is a vec4 to match std140.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct LightUniform {
+    pub direction: [f32; 4],
+    pub color: [f32; 4],
+    pub ambient: [f32; 4],
+}
+
+/// One directional light, like the sun, plus a flat ambient term.
+#[derive(Clone, Copy, Debug)]
+pub struct DirectionalLight {
+    /// The direction the light travels in, does not need to be normalized.
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub ambient: [f32; 3],
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vector3<f32>, color: [f32; 3], intensity: f32, ambient: [f32; 3]) -> Self {
+        Self {
+            direction,
+            color,
+            intensity,
+            ambient,
+        }
+    }
+
+    pub fn uniform(&self) -> LightUniform {
+        let direction = match self.direction.magnitude2() > 0.0 {
+            true => self.direction.normalize(),
+            false => Vector3::new(0.0, -1.0, 0.0),
+        };
+        let [r, g, b] = self.color;
+        let [ambient_r, ambient_g, ambient_b] = self.ambient;
+
+        LightUniform {
+            direction: [direction.x, direction.y, direction.z, 0.0],
+            color: [r * self.intensity, g * self.intensity, b * self.intensity, 1.0],
+            ambient: [ambient_r, ambient_g, ambient_b, 1.0],
+        }
+    }
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self::new(Vector3::new(-0.4, -1.0, -0.6), [1.0, 1.0, 1.0], 1.0, [0.15, 0.15, 0.15])
+    }
+}
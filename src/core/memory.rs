@@ -0,0 +1,158 @@
+use ash::vk;
+
+use gpu_allocator::vulkan::Allocation;
+use gpu_allocator::MemoryLocation;
+
+use crate::core::device::RendererDevice;
+
+use anyhow::Result;
+
+pub struct AllocatedBuffer {
+    pub buffer: vk::Buffer,
+    pub allocation: Option<Allocation>,
+    pub size: vk::DeviceSize,
+}
+
+impl AllocatedBuffer {
+    pub fn new(
+        device: &RendererDevice,
+        name: &str,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        location: MemoryLocation,
+    ) -> Result<AllocatedBuffer> {
+        let buffer_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let buffer = unsafe {
+            device.logical_device.create_buffer(&buffer_info, None)?
+        };
+
+        let requirements = unsafe { device.logical_device.get_buffer_memory_requirements(buffer) };
+        let allocation = match device.allocate(name, requirements, location, true) {
+            Err(error) => {
+                unsafe { device.logical_device.destroy_buffer(buffer, None) };
+                return Err(error);
+            },
+            Ok(allocation) => allocation
+        };
+
+        let bound = unsafe {
+            device.logical_device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
+        };
+        if let Err(error) = bound {
+            unsafe { device.logical_device.destroy_buffer(buffer, None) };
+            device.free(allocation);
+            return Err(error.into());
+        }
+
+        Ok(AllocatedBuffer {
+            buffer,
+            allocation: Some(allocation),
+            size,
+        })
+    }
+
+    /// Copies `data` into the start of the buffer. Only valid for host visible locations.
+    pub fn write<T: Copy>(&mut self, data: &[T]) -> Result<()> {
+        let size = std::mem::size_of_val(data);
+        if size as vk::DeviceSize > self.size {
+            anyhow::bail!("Writing {} bytes into a buffer of {} bytes", size, self.size);
+        }
+
+        let mapped = match self.allocation.as_ref().and_then(|allocation| allocation.mapped_ptr()) {
+            None => anyhow::bail!("Buffer memory is not host visible"),
+            Some(ptr) => ptr
+        };
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr() as *const u8, mapped.as_ptr() as *mut u8, size);
+        }
+
+        Ok(())
+    }
+
+    pub fn read(&self) -> Result<Vec<u8>> {
+        match self.allocation.as_ref().and_then(|allocation| allocation.mapped_slice()) {
+            None => anyhow::bail!("Buffer memory is not host visible"),
+            Some(slice) => Ok(slice[..self.size as usize].to_vec())
+        }
+    }
+
+    pub unsafe fn destroy(&mut self, device: &RendererDevice) {
+        device.logical_device.destroy_buffer(self.buffer, None);
+        self.buffer = vk::Buffer::null();
+        if let Some(allocation) = self.allocation.take() {
+            device.free(allocation);
+        }
+    }
+}
+
+/// Freeing needs the device, so it can't happen here: this only reports a missed `destroy`.
+impl Drop for AllocatedBuffer {
+    fn drop(&mut self) {
+        if let Some(allocation) = &self.allocation {
+            println!("Buffer {:?} descartado sem destroy, {} bytes vazados", self.buffer, allocation.size());
+        }
+    }
+}
+
+pub struct AllocatedImage {
+    pub image: vk::Image,
+    pub allocation: Option<Allocation>,
+}
+
+impl AllocatedImage {
+    pub fn new(
+        device: &RendererDevice,
+        name: &str,
+        image_info: &vk::ImageCreateInfo,
+        location: MemoryLocation,
+    ) -> Result<AllocatedImage> {
+        let image = unsafe {
+            device.logical_device.create_image(image_info, None)?
+        };
+
+        let requirements = unsafe { device.logical_device.get_image_memory_requirements(image) };
+        let linear = image_info.tiling == vk::ImageTiling::LINEAR;
+        let allocation = match device.allocate(name, requirements, location, linear) {
+            Err(error) => {
+                unsafe { device.logical_device.destroy_image(image, None) };
+                return Err(error);
+            },
+            Ok(allocation) => allocation
+        };
+
+        let bound = unsafe {
+            device.logical_device.bind_image_memory(image, allocation.memory(), allocation.offset())
+        };
+        if let Err(error) = bound {
+            unsafe { device.logical_device.destroy_image(image, None) };
+            device.free(allocation);
+            return Err(error.into());
+        }
+
+        Ok(AllocatedImage {
+            image,
+            allocation: Some(allocation),
+        })
+    }
+
+    pub unsafe fn destroy(&mut self, device: &RendererDevice) {
+        device.logical_device.destroy_image(self.image, None);
+        self.image = vk::Image::null();
+        if let Some(allocation) = self.allocation.take() {
+            device.free(allocation);
+        }
+    }
+}
+
+impl Drop for AllocatedImage {
+    fn drop(&mut self) {
+        if let Some(allocation) = &self.allocation {
+            println!("Imagem {:?} descartada sem destroy, {} bytes vazados", self.image, allocation.size());
+        }
+    }
+}
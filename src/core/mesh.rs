@@ -0,0 +1,94 @@
+use ash::vk;
+
+use crate::core::commandpool::CommandPools;
+use crate::core::device::RendererDevice;
+use crate::core::memory::AllocatedBuffer;
+use crate::core::object::model::IndexFormat;
+use crate::core::object::vertex::Vertex;
+use crate::core::VulkanRenderer;
+
+use anyhow::Result;
+
+/// Vertex and index data living in device local memory.
+pub struct Mesh {
+    pub vertex_buffer: AllocatedBuffer,
+    pub index_buffer: AllocatedBuffer,
+    pub index_count: u32,
+    pub index_type: vk::IndexType,
+}
+
+impl Mesh {
+    pub fn new(
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        vertices: &[Vertex],
+        indices: &[u32],
+        index_format: IndexFormat,
+    ) -> Result<Mesh> {
+        if vertices.is_empty() || indices.is_empty() {
+            anyhow::bail!("Cannot upload an empty mesh");
+        }
+
+        // converted before anything is uploaded, so a bad index leaves nothing to free
+        let short_indices = match index_format {
+            IndexFormat::U32 => None,
+            IndexFormat::U16 => {
+                let short_indices = indices.iter()
+                    .map(|&index| u16::try_from(index))
+                    .collect::<Result<Vec<u16>, _>>()
+                    .map_err(|_| anyhow::anyhow!("Index out of range for 16-bit indices"))?;
+                Some(short_indices)
+            },
+        };
+
+        let mut vertex_buffer = VulkanRenderer::create_device_local_buffer_with_data(
+            device,
+            command_pools,
+            "Vertex buffer",
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vertices,
+        )?;
+
+        let index_buffer = match &short_indices {
+            None => VulkanRenderer::create_device_local_buffer_with_data(
+                device,
+                command_pools,
+                "Index buffer",
+                vk::BufferUsageFlags::INDEX_BUFFER,
+                indices,
+            ),
+            Some(short_indices) => VulkanRenderer::create_device_local_buffer_with_data(
+                device,
+                command_pools,
+                "Index buffer",
+                vk::BufferUsageFlags::INDEX_BUFFER,
+                short_indices,
+            ),
+        };
+
+        let index_buffer = match index_buffer {
+            Ok(buffer) => buffer,
+            Err(error) => {
+                unsafe { vertex_buffer.destroy(device) };
+                return Err(error);
+            }
+        };
+
+        let index_type = match short_indices {
+            None => vk::IndexType::UINT32,
+            Some(_) => vk::IndexType::UINT16,
+        };
+
+        Ok(Mesh {
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            index_type,
+        })
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        self.index_buffer.destroy(device);
+        self.vertex_buffer.destroy(device);
+    }
+}
@@ -4,39 +4,83 @@ pub mod window;
 pub mod swapchain;
 pub mod pipeline;
 pub mod shader;
+pub mod sprite;
 pub mod commandpool;
+pub mod config;
+pub mod camera;
+pub mod capabilities;
+pub mod camera2d;
+pub mod depth;
+pub mod descriptor;
+pub mod draw;
+pub mod frame;
+pub mod light;
+pub mod memory;
+pub mod mesh;
+pub mod offscreen;
+pub mod recorder;
+pub mod rendermodel;
+pub mod texture;
+pub mod uniform;
 pub mod object;
 
 use device::RendererDevice;
 use window::RendererWindow;
-use swapchain::RendererSwapchain;
+use swapchain::{RendererSwapchain, SwapchainConfig};
 use debug::RendererDebug;
-use pipeline::RendererPipeline;
+use pipeline::{BlendMode, DepthState, PipelineBuilder, RendererPipeline};
+use capabilities::DeviceCapabilities;
 use commandpool::CommandPools;
+use config::RendererConfig;
+use depth::DepthBuffer;
+use recorder::CommandRecorder;
+use offscreen::RendererOffscreen;
+use draw::{DrawCommand, DrawList, MeshHandle, PipelineHandle, PushConstants, TextureHandle};
+use frame::{FrameContext, FrameResult};
+use light::DirectionalLight;
+use mesh::Mesh;
+use rendermodel::{RenderMaterial, RenderModel, RenderPart};
+use sprite::SpriteBuffers;
+use memory::AllocatedBuffer;
+use camera::Camera;
+use descriptor::RendererDescriptors;
+use texture::Texture;
+use uniform::{UniformBufferObject, UniformBuffers};
 
 
 use ash::vk;
 use ash::extensions::{ext, khr};
 use anyhow::Result;
 use std::ffi;
-use std::ptr::copy_nonoverlapping as memcpy;
+use gpu_allocator::MemoryLocation;
 use raw_window_handle::HasRawDisplayHandle;
 
+use self::object::material::TextureSource;
+use self::object::model::{IndexFormat, Model};
 use self::object::vertex::{Vertex};
 
 pub struct VulkanRenderer {
     pub instance: ash::Instance,
     pub main_device: RendererDevice,
-    pub window: RendererWindow,
-    pub swapchain: RendererSwapchain,
+    pub window: Option<RendererWindow>,
+    pub swapchain: Option<RendererSwapchain>,
+    pub offscreen: Option<RendererOffscreen>,
     pub debug: RendererDebug,
     pub render_pass: vk::RenderPass,
-    pub graphics_pipeline: RendererPipeline,
+    pub depth_format: vk::Format,
+    pub pipelines: Vec<RendererPipeline>,
     pub command_pools: CommandPools,
-    pub graphics_command_buffers: Vec<vk::CommandBuffer>,
-    pub vertex_buffer: vk::Buffer,
-    pub index_buffer: vk::Buffer,
-    pub model_index_count: usize,
+    pub frames: FrameContext,
+    pub uniform_buffers: UniformBuffers,
+    pub descriptors: RendererDescriptors,
+    pub textures: Vec<Texture>,
+    pub meshes: Vec<Mesh>,
+    pub sprite_buffers: SpriteBuffers,
+    pub camera: Camera,
+    /// The light used by `PipelineHandle::LIT`, picked up from the next frame on.
+    pub light: DirectionalLight,
+    pub swapchain_dirty: bool,
+    pub swapchain_config: SwapchainConfig,
 }
 
 
@@ -50,24 +94,64 @@ impl VulkanRenderer {
     fn used_extensions() -> Vec<*const i8> {
         vec![
             ext::DebugUtils::name().as_ptr(),
-            khr::Surface::name().as_ptr(),
         ]
     }
 
 
     pub fn new() -> Result<Self> {
+        Self::with_config(RendererConfig::default())
+    }
+
+    pub fn with_config(config: RendererConfig) -> Result<Self> {
         let (event_loop, window) = RendererWindow::create_window()?;
         let raw_display_handle = window.raw_display_handle();
         window.set_title("Pencilmake");
 
-        
+        let used_layer_names = Self::used_layer_names();
+        let used_layers: Vec<_> = used_layer_names.iter()
+            .map(|layer_name| layer_name.as_ptr())
+            .collect();
+
+        let mut used_extensions = Self::used_extensions();
+        used_extensions.push(khr::Surface::name().as_ptr());
+
+        let extension_names = ash_window::enumerate_required_extensions(raw_display_handle)?;
+        for extension_name in extension_names.iter() {
+            used_extensions.push(*extension_name);
+        };
+
+        Self::print_layers_and_extensions(&used_layers, &used_extensions);
+
+        let entry = ash::Entry::linked();
+        let instance = Self::create_instance(&entry, &used_layers, &used_extensions)?;
+        let window = RendererWindow::new(event_loop, window, &entry, &instance)?;
+
+        Self::init(&entry, instance, &config, Some(window), None)
+    }
 
+    /// Creates a renderer without a window or surface, drawing into an offscreen color image
+    /// of the given size. Use `read_pixels` to get the rendered frame back on the CPU.
+    pub fn new_headless(width: u32, height: u32) -> Result<Self> {
+        Self::new_headless_with_config(width, height, RendererConfig::default())
+    }
+
+    pub fn new_headless_with_config(width: u32, height: u32, config: RendererConfig) -> Result<Self> {
         let used_layer_names = Self::used_layer_names();
         let used_layers: Vec<_> = used_layer_names.iter()
             .map(|layer_name| layer_name.as_ptr())
             .collect();
-        
 
+        let used_extensions = Self::used_extensions();
+
+        Self::print_layers_and_extensions(&used_layers, &used_extensions);
+
+        let entry = ash::Entry::linked();
+        let instance = Self::create_instance(&entry, &used_layers, &used_extensions)?;
+
+        Self::init(&entry, instance, &config, None, Some(vk::Extent2D { width, height }))
+    }
+
+    fn print_layers_and_extensions(used_layers: &[*const i8], used_extensions: &[*const i8]) {
         println!("Used layers:");
         for layer in used_layers.iter() {
             unsafe {
@@ -75,14 +159,6 @@ impl VulkanRenderer {
                 println!("  {}", layer_name);
             }
         }
-            
-        let mut used_extensions = Self::used_extensions();
-
-
-        let extension_names = ash_window::enumerate_required_extensions(raw_display_handle)?;
-        for extension_name in extension_names.iter() {
-            used_extensions.push(*extension_name);
-        };
 
         println!("Used extensions:");
         for extension in used_extensions.iter() {
@@ -91,64 +167,562 @@ impl VulkanRenderer {
                 println!("  {}", extension_name);
             }
         }
-        let entry = ash::Entry::linked();
-        let instance = Self::create_instance(&entry, &used_layers, &used_extensions)?;
-        let window = RendererWindow::new(event_loop, window, &entry, &instance)?;
-        let debug = RendererDebug::new(&entry, &instance)?;
+    }
 
-        let main_device = match RendererDevice::new(&instance, &used_layers)? {
-            None => panic!("Nenhum dispositivo foi encontrado"),
+    fn init(
+        entry: &ash::Entry,
+        instance: ash::Instance,
+        config: &RendererConfig,
+        window: Option<RendererWindow>,
+        offscreen_extent: Option<vk::Extent2D>,
+    ) -> Result<Self> {
+        let debug = RendererDebug::new(entry, &instance)?;
+
+        let main_device = match RendererDevice::new(&instance, window.as_ref(), &config.device, &config.requirements)? {
+            None => anyhow::bail!("Nenhum dispositivo compatível foi encontrado"),
             Some(dev) => dev
         };
 
-        let render_pass = Self::create_render_pass(&main_device, &window)?;
+        let depth_format = DepthBuffer::find_format(&instance, &main_device)?;
+
+        let (swapchain, offscreen, render_pass) = match (&window, offscreen_extent) {
+            (Some(window), _) => {
+                let formats = window.formats(main_device.physical_device)?;
+                let format = match config.swapchain.choose_format(&formats) {
+                    None => anyhow::bail!("The window surface supports no formats"),
+                    Some(format) => format
+                };
+                let render_pass = Self::create_render_pass(&main_device, format.format, depth_format, vk::ImageLayout::PRESENT_SRC_KHR)?;
+
+                let mut swapchain = RendererSwapchain::new(&instance, &main_device, window, format, &config.swapchain, vk::SwapchainKHR::null())?;
+                swapchain.create_framebuffers(&main_device, render_pass, depth_format)?;
+
+                (Some(swapchain), None, render_pass)
+            },
+            (None, Some(extent)) => {
+                let render_pass = Self::create_render_pass(&main_device, RendererOffscreen::FORMAT, depth_format, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)?;
+
+                let mut offscreen = RendererOffscreen::new(&main_device, extent)?;
+                offscreen.create_framebuffers(&main_device, render_pass, depth_format)?;
 
-        let mut swapchain = RendererSwapchain::new(&instance, &main_device, &window)?;
-        swapchain.create_framebuffers(&main_device, render_pass)?;
+                (None, Some(offscreen), render_pass)
+            },
+            (None, None) => anyhow::bail!("A renderer needs either a window or an offscreen extent"),
+        };
+
+        let framebuffer_count = match (&swapchain, &offscreen) {
+            (Some(swapchain), _) => swapchain.framebuffers.len(),
+            (_, Some(offscreen)) => offscreen.framebuffers.len(),
+            _ => unreachable!(),
+        };
 
-        let graphics_pipeline = RendererPipeline::new(&main_device, swapchain.extent, render_pass)?;
         let command_pools = CommandPools::new(&main_device)?;
 
-        println!("There is {} framebuffers", swapchain.framebuffers.len());
-        let graphics_command_buffers = CommandPools::create_command_buffers(&main_device, command_pools.graphics, swapchain.framebuffers.len() as u32)?;
-
-        let (vertices, indices) = object::model::load_model("models", "duck.obj");
-        let (vertex_buffer, _) = Self::create_vertex_buffer(
-            &main_device, 
-            &instance,
-            command_pools.graphics, 
-            main_device.graphics_queue,
-            &vertices,
-        );
-
-        let (index_buffer, _) = Self::create_index_buffer(
-            &main_device,
-            &instance,
-            command_pools.graphics,
-            main_device.graphics_queue,
-            &indices
-        );
-
-        let renderer = Self {
+        // command buffers, uniform buffers and descriptor sets are per frame in flight,
+        // since every frame is recorded again from the draw list
+        println!("There is {} framebuffers", framebuffer_count);
+        let frames_in_flight = FrameContext::DEFAULT_FRAMES_IN_FLIGHT;
+        let frames = FrameContext::new(&main_device, command_pools.graphics, frames_in_flight, framebuffer_count)?;
+        let uniform_buffers = UniformBuffers::new(&main_device, frames_in_flight)?;
+
+        let mut descriptors = RendererDescriptors::new(&main_device, &uniform_buffers)?;
+        let white = Texture::white(&instance, &main_device, &command_pools)?;
+        descriptors.add_texture(&main_device, &uniform_buffers, &white)?;
+
+        println!("{}", main_device.memory_report());
+
+        let mut renderer = Self {
             instance,
             main_device,
             window,
             debug,
             swapchain,
+            offscreen,
             render_pass,
-            graphics_pipeline,
+            depth_format,
+            pipelines: vec![],
             command_pools,
-            graphics_command_buffers,
-            vertex_buffer,
-            index_buffer,
-            model_index_count: indices.len()
+            frames,
+            uniform_buffers,
+            descriptors,
+            textures: vec![white],
+            meshes: vec![],
+            sprite_buffers: SpriteBuffers::new(frames_in_flight),
+            camera: Camera::default(),
+            light: DirectionalLight::default(),
+            swapchain_dirty: false,
+            swapchain_config: config.swapchain.clone(),
         };
 
-        renderer.fill_command_buffers().expect("Falha ao preencher buffers de comando");
+        // in the order of the PipelineHandle constants
+        renderer.create_pipeline(PipelineBuilder::mesh(DepthState::default()))?;
+        renderer.create_pipeline(PipelineBuilder::lit(DepthState::default()))?;
+        for blend_mode in BlendMode::ALL {
+            renderer.create_pipeline(PipelineBuilder::sprite(blend_mode))?;
+        }
+
         Ok(renderer)
     }
 
-    fn create_instance(entry: &ash::Entry, layer_name_pts: &Vec<*const i8>, extension_name_pts: &Vec<*const i8>) -> Result<ash::Instance> {
+    pub fn extent(&self) -> vk::Extent2D {
+        match (&self.swapchain, &self.offscreen) {
+            (Some(swapchain), _) => swapchain.extent,
+            (_, Some(offscreen)) => offscreen.extent,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Physical pixels per logical pixel, 1.0 on headless renderers. `extent` is in physical
+    /// pixels, so UI laid out in logical pixels should be scaled by this to stay crisp.
+    pub fn scale_factor(&self) -> f32 {
+        match &self.window {
+            None => 1.0,
+            Some(window) => window.scale_factor() as f32,
+        }
+    }
+
+    /// The render area in logical pixels, i.e. `extent` divided by `scale_factor`.
+    pub fn logical_size(&self) -> [f32; 2] {
+        let extent = self.extent();
+        let scale_factor = self.scale_factor();
+
+        [extent.width as f32 / scale_factor, extent.height as f32 / scale_factor]
+    }
+
+    pub fn framebuffers(&self) -> &[vk::Framebuffer] {
+        match (&self.swapchain, &self.offscreen) {
+            (Some(swapchain), _) => &swapchain.framebuffers,
+            (_, Some(offscreen)) => &offscreen.framebuffers,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Marks the swapchain as stale so it is rebuilt before the next frame. Call it on resizes
+    /// and scale factor changes, both change the window's size in physical pixels.
+    pub fn handle_resize(&mut self) {
+        if self.swapchain.is_some() {
+            self.swapchain_dirty = true;
+        }
+    }
+
+    /// Rebuilds the swapchain and its framebuffers.
+    /// Returns `false` without touching anything while the window is minimized.
+    pub fn recreate_swapchain(&mut self) -> Result<bool> {
+        let window = match &self.window {
+            None => return Ok(false),
+            Some(window) => window
+        };
+
+        if window.is_minimized() {
+            return Ok(false);
+        }
+
+        let capabilities = window.capabilities(self.main_device.physical_device)?;
+        let extent = window.surface_extent(&capabilities);
+        if extent.width == 0 || extent.height == 0 {
+            return Ok(false);
+        }
+
+        println!("Recriando swapchain...");
+        unsafe {
+            self.main_device.logical_device.device_wait_idle()?;
+        }
+
+        let old_swapchain = match &self.swapchain {
+            None => anyhow::bail!("Recreating a swapchain that was never created"),
+            Some(swapchain) => swapchain
+        };
+
+        // the render pass and every pipeline were built for the current format, so it stays.
+        // Handing the old swapchain over lets the driver reuse its resources. It stays in place
+        // until the new one is complete, so on failure `extent` and the framebuffers still
+        // answer and the next frame simply tries again
+        let mut swapchain = RendererSwapchain::new(
+            &self.instance,
+            &self.main_device,
+            window,
+            old_swapchain.format,
+            &self.swapchain_config,
+            old_swapchain.swapchain,
+        )?;
+        if let Err(error) = swapchain.create_framebuffers(&self.main_device, self.render_pass, self.depth_format) {
+            unsafe { swapchain.cleanup(&self.main_device) };
+            return Err(error);
+        }
+
+        if let Some(mut old_swapchain) = self.swapchain.replace(swapchain) {
+            unsafe { old_swapchain.cleanup(&self.main_device) };
+        }
+
+        let image_count = self.framebuffers().len();
+        self.frames.reset_images(image_count);
+
+        self.swapchain_dirty = false;
+        Ok(true)
+    }
+
+    /// Applies `config` from the next frame on, e.g. from a settings menu. Vsync and the image
+    /// count take effect right away; the surface format is only negotiated when the renderer
+    /// is created, since the render pass and pipelines are built for it.
+    pub fn set_swapchain_config(&mut self, config: SwapchainConfig) {
+        self.swapchain_config = config;
+        self.swapchain_dirty |= self.swapchain.is_some();
+    }
+
+    /// The present mode, format and image count the swapchain ended up with, `None` on
+    /// headless renderers.
+    pub fn swapchain_mode(&self) -> Option<(vk::PresentModeKHR, vk::SurfaceFormatKHR, u32)> {
+        self.swapchain.as_ref().map(|swapchain| (swapchain.present_mode, swapchain.format, swapchain.image_count))
+    }
+
+    /// Changes how many frames the CPU may record ahead of the GPU.
+    pub fn set_frames_in_flight(&mut self, frames_in_flight: usize) -> Result<()> {
+        if frames_in_flight == self.frames.frames_in_flight() {
+            return Ok(());
+        }
+
+        unsafe {
+            self.main_device.logical_device.device_wait_idle()?;
+        }
+
+        // everything is built first and only swapped in once it all exists, so a failure
+        // leaves the renderer running with the old frame count
+        let frames = FrameContext::new(&self.main_device, self.command_pools.graphics, frames_in_flight, self.framebuffers().len())?;
+
+        let mut uniform_buffers = match UniformBuffers::new(&self.main_device, frames_in_flight) {
+            Ok(uniform_buffers) => uniform_buffers,
+            Err(error) => {
+                unsafe { frames.cleanup(&self.main_device, self.command_pools.graphics) };
+                return Err(error);
+            }
+        };
+
+        let descriptors = match self.descriptors.reallocate(&self.main_device, &uniform_buffers, &self.textures) {
+            Ok(descriptors) => descriptors,
+            Err(error) => {
+                unsafe {
+                    uniform_buffers.cleanup(&self.main_device);
+                    frames.cleanup(&self.main_device, self.command_pools.graphics);
+                }
+                return Err(error);
+            }
+        };
+
+        let old_frames = std::mem::replace(&mut self.frames, frames);
+        let mut old_uniform_buffers = std::mem::replace(&mut self.uniform_buffers, uniform_buffers);
+        let old_descriptors = std::mem::replace(&mut self.descriptors, descriptors);
+        unsafe {
+            old_frames.cleanup(&self.main_device, self.command_pools.graphics);
+            old_uniform_buffers.cleanup(&self.main_device);
+            old_descriptors.cleanup_pool(&self.main_device);
+        }
+
+        unsafe { self.sprite_buffers.cleanup(&self.main_device) };
+        self.sprite_buffers = SpriteBuffers::new(frames_in_flight);
+
+        Ok(())
+    }
+
+    /// Records `draw_list` into the current frame's command buffer, then submits and presents it.
+    /// Swapchain recreation, minimized windows and frame pacing are handled here, so callers
+    /// never deal with the Vulkan handles themselves.
+    pub fn draw_frame(&mut self, draw_list: &DrawList) -> Result<FrameResult> {
+        // checked up front, so a bad handle never leaves a frame acquired but not submitted
+        self.validate_draw_list(draw_list)?;
+
+        if self.swapchain_dirty && !self.recreate_swapchain()? {
+            return Ok(FrameResult::Skipped);
+        }
+
+        if let Some(window) = &self.window {
+            if window.is_minimized() {
+                return Ok(FrameResult::Skipped);
+            }
+        }
+
+        // wait until this frame slot, and its command buffer, is free again:
+        self.frames.wait_current(&self.main_device)?;
+        self.sprite_buffers.upload(&self.main_device, self.frames.current_frame, &draw_list.sprite_vertices)?;
+
+        let swapchain = match &self.swapchain {
+            None => {
+                // headless: the offscreen image is the only target
+                self.frames.claim_image(&self.main_device, 0)?;
+                self.update_uniforms()?;
+                self.record_frame(0, draw_list)?;
+                self.submit_frame(false)?;
+                self.frames.advance();
+
+                if let Some(offscreen) = &mut self.offscreen {
+                    offscreen.rendered = true;
+                }
+
+                return Ok(FrameResult::Presented);
+            },
+            Some(swapchain) => swapchain
+        };
+
+        // acquiring next image:
+        let acquired = unsafe {
+            swapchain.swapchain_loader.acquire_next_image(
+                swapchain.swapchain,
+                u64::MAX,
+                self.frames.current().image_available,
+                vk::Fence::null(),
+            )
+        };
+
+        let image_index = match acquired {
+            Ok((image_index, _)) => image_index,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.swapchain_dirty = true;
+                return Ok(FrameResult::OutOfDate);
+            },
+            Err(error) => return Err(error.into()),
+        };
+
+        self.frames.claim_image(&self.main_device, image_index)?;
+        self.update_uniforms()?;
+        self.record_frame(image_index, draw_list)?;
+        self.submit_frame(true)?;
+
+        // present:
+        let swapchain = self.swapchain.as_ref().unwrap();
+        let semaphores_finished = [self.frames.current().rendering_finished];
+        let swapchains = [swapchain.swapchain];
+        let indices = [image_index];
+
+        let present_info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(&semaphores_finished)
+            .swapchains(&swapchains)
+            .image_indices(&indices);
+
+        let presented = unsafe {
+            swapchain.swapchain_loader
+                .queue_present(self.main_device.present_queue, &present_info)
+        };
+
+        self.frames.advance();
+
+        match presented {
+            Ok(false) => Ok(FrameResult::Presented),
+            Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.swapchain_dirty = true;
+                Ok(FrameResult::OutOfDate)
+            },
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn validate_draw_list(&self, draw_list: &DrawList) -> Result<()> {
+        for command in &draw_list.commands {
+            match command {
+                DrawCommand::SetPipeline(pipeline) if pipeline.0 >= self.pipelines.len() => {
+                    anyhow::bail!("Unknown pipeline {:?}", pipeline);
+                },
+                DrawCommand::DrawMesh { mesh, texture, .. } => {
+                    if mesh.0 >= self.meshes.len() {
+                        anyhow::bail!("Unknown mesh {:?}", mesh);
+                    }
+                    if texture.0 >= self.textures.len() {
+                        anyhow::bail!("Unknown texture {:?}", texture);
+                    }
+                },
+                DrawCommand::DrawSprites { texture, first_vertex, vertex_count, .. } => {
+                    if texture.0 >= self.textures.len() {
+                        anyhow::bail!("Unknown texture {:?}", texture);
+                    }
+                    // caller supplied, so the end may not even fit in a u32
+                    match first_vertex.checked_add(*vertex_count) {
+                        Some(end) if end as usize <= draw_list.sprite_vertices.len() => {},
+                        _ => anyhow::bail!("Sprite draw past the end of the draw list vertices"),
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Uploads a mesh to device local memory. The handle can be drawn through a `DrawList`.
+    /// With `IndexFormat::U16` every index must fit in 16 bits.
+    pub fn upload_mesh(&mut self, vertices: &[Vertex], indices: &[u32], index_format: IndexFormat) -> Result<MeshHandle> {
+        let mesh = Mesh::new(
+            &self.main_device,
+            &self.command_pools,
+            vertices,
+            indices,
+            index_format,
+        )?;
+
+        self.meshes.push(mesh);
+        Ok(MeshHandle(self.meshes.len() - 1))
+    }
+
+    /// Uploads every submesh and texture of `model`.
+    pub fn upload_model(&mut self, model: &Model) -> Result<RenderModel> {
+        let mut textures = Vec::with_capacity(model.textures.len());
+        for (i, source) in model.textures.iter().enumerate() {
+            let texture = match source {
+                TextureSource::File(path) => self.load_texture(path)?,
+                TextureSource::Rgba { width, height, pixels } => {
+                    let texture = Texture::from_rgba(
+                        &self.instance,
+                        &self.main_device,
+                        &self.command_pools,
+                        &format!("Model texture {}", i),
+                        *width,
+                        *height,
+                        pixels,
+                    )?;
+                    self.add_texture(texture)?
+                }
+            };
+
+            textures.push(texture);
+        }
+
+        let materials = model.materials.iter()
+            .map(|material| RenderMaterial {
+                texture: match material.diffuse_texture {
+                    None => TextureHandle::WHITE,
+                    Some(index) => textures[index],
+                },
+                color: material.color(),
+            })
+            .collect();
+
+        let mut parts = Vec::with_capacity(model.meshes.len());
+        for submesh in &model.meshes {
+            if submesh.indices.is_empty() {
+                continue;
+            }
+
+            parts.push(RenderPart {
+                mesh: self.upload_mesh(&submesh.vertices, &submesh.indices, submesh.index_format)?,
+                material: submesh.material,
+            });
+        }
+
+        Ok(RenderModel {
+            parts,
+            materials,
+        })
+    }
+
+    /// Builds a pipeline against the renderer's render pass. The descriptor set layout and the
+    /// per-draw transform push constant are added here, so the builder only describes shaders and state.
+    pub fn create_pipeline(&mut self, builder: PipelineBuilder) -> Result<PipelineHandle> {
+        let pipeline = builder
+            .descriptor_set_layouts(&[self.descriptors.layout])
+            .push_constant_range(PushConstants::range())
+            .build(&self.main_device, self.render_pass)?;
+
+        self.pipelines.push(pipeline);
+        Ok(PipelineHandle(self.pipelines.len() - 1))
+    }
+
+    /// Loads a PNG or JPEG from the assets directory and registers it for drawing.
+    pub fn load_texture<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<TextureHandle> {
+        let texture = Texture::from_file(
+            &self.instance,
+            &self.main_device,
+            &self.command_pools,
+            path,
+        )?;
+
+        self.add_texture(texture)
+    }
+
+    /// Registers `texture`, which is then bound to the sampler at binding 1 by draws using the handle.
+    pub fn add_texture(&mut self, texture: Texture) -> Result<TextureHandle> {
+        let index = match self.descriptors.add_texture(&self.main_device, &self.uniform_buffers, &texture) {
+            Ok(index) => index,
+            Err(error) => {
+                let mut texture = texture;
+                unsafe { texture.cleanup(&self.main_device) };
+                return Err(error);
+            }
+        };
+
+        self.textures.push(texture);
+        Ok(TextureHandle(index))
+    }
+
+    /// Writes the camera and light into the current frame's uniform buffers.
+    fn update_uniforms(&mut self) -> Result<()> {
+        let extent = self.extent();
+        let aspect = extent.width as f32 / extent.height as f32;
+
+        let ubo = UniformBufferObject {
+            view: self.camera.view_matrix(),
+            proj: self.camera.projection_matrix(aspect),
+        };
+
+        self.uniform_buffers.update(self.frames.current_frame, &ubo)?;
+        self.uniform_buffers.update_light(self.frames.current_frame, &self.light.uniform())
+    }
+
+    fn submit_frame(&self, present: bool) -> Result<()> {
+        let frame = self.frames.current();
+
+        let semaphores_available = [frame.image_available];
+        let waiting_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+        let semaphores_finished = [frame.rendering_finished];
+        let command_buffers = [frame.command_buffer];
+
+        let mut submit_info = vk::SubmitInfo::builder()
+            .command_buffers(&command_buffers);
+
+        if present {
+            submit_info = submit_info
+                .wait_semaphores(&semaphores_available)
+                .wait_dst_stage_mask(&waiting_stages)
+                .signal_semaphores(&semaphores_finished);
+        }
+
+        unsafe {
+            self.main_device.logical_device.queue_submit(
+                self.main_device.graphics_queue,
+                &[submit_info.build()],
+                frame.in_flight,
+            )?;
+        };
+
+        Ok(())
+    }
+
+    pub fn acquire_event_loop(&mut self) -> Result<winit::event_loop::EventLoop<()>> {
+        match self.window.as_mut() {
+            None => anyhow::bail!("Headless renderers have no event loop"),
+            Some(window) => window.acquire_event_loop()
+        }
+    }
+
+    pub fn request_redraw(&self) {
+        if let Some(window) = &self.window {
+            if !window.is_minimized() {
+                window.window.request_redraw();
+            }
+        }
+    }
+
+    /// Reads the last frame drawn by `draw_frame` on a headless renderer back into a tightly packed RGBA8 buffer.
+    pub fn read_pixels(&self) -> Result<Vec<u8>> {
+        let offscreen = match &self.offscreen {
+            None => anyhow::bail!("read_pixels is only available on headless renderers"),
+            Some(offscreen) => offscreen
+        };
+
+        offscreen.read_pixels(
+            &self.main_device,
+            self.command_pools.graphics,
+            self.main_device.graphics_queue,
+        )
+    }
+
+    fn create_instance(entry: &ash::Entry, layer_name_pts: &[*const i8], extension_name_pts: &[*const i8]) -> Result<ash::Instance> {
         let app_name = std::ffi::CString::new("Pencilmake")?;
         let engine_name = std::ffi::CString::new("Pencilmake Engine")?;
 
@@ -157,7 +731,7 @@ impl VulkanRenderer {
             .application_version(vk::make_api_version(0, 1, 0, 0))
             .engine_name(&engine_name)
             .engine_version(vk::make_api_version(0, 1, 0, 0))
-            .api_version(vk::API_VERSION_1_3);
+            .api_version(DeviceCapabilities::INSTANCE_API_VERSION);
         
         let instance_info = vk::InstanceCreateInfo::builder()
             .application_info(&app_info)
@@ -170,21 +744,38 @@ impl VulkanRenderer {
         Ok(instance)
     }
 
-    fn create_render_pass(device: &RendererDevice, window: &RendererWindow) -> Result<vk::RenderPass> {
-        let formats = window.formats(device.physical_device)?;
-        let format = formats.first().unwrap();
+    fn create_render_pass(
+        device: &RendererDevice,
+        format: vk::Format,
+        depth_format: vk::Format,
+        final_layout: vk::ImageLayout
+    ) -> Result<vk::RenderPass> {
+        let stencil_load_op = match DepthBuffer::has_stencil(depth_format) {
+            true => vk::AttachmentLoadOp::CLEAR,
+            false => vk::AttachmentLoadOp::DONT_CARE,
+        };
 
         let attachments = [
             vk::AttachmentDescription::builder()
-                .format(format.format)
+                .format(format)
                 .load_op(vk::AttachmentLoadOp::CLEAR)
                 .store_op(vk::AttachmentStoreOp::STORE)
                 .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
                 .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
                 .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                .final_layout(final_layout)
                 .samples(vk::SampleCountFlags::TYPE_1)
-                .build()
+                .build(),
+            vk::AttachmentDescription::builder()
+                .format(depth_format)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .stencil_load_op(stencil_load_op)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .build(),
         ];
 
         let color_attachment_references = [vk::AttachmentReference {
@@ -192,9 +783,15 @@ impl VulkanRenderer {
             layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
         }];
 
+        let depth_attachment_reference = vk::AttachmentReference {
+            attachment: 1,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+
         let subpasses = [
             vk::SubpassDescription::builder()
                 .color_attachments(&color_attachment_references)
+                .depth_stencil_attachment(&depth_attachment_reference)
                 .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
                 .build()
         ];
@@ -202,10 +799,15 @@ impl VulkanRenderer {
         let subpass_dependencies = [
             vk::SubpassDependency::builder()
                 .src_subpass(vk::SUBPASS_EXTERNAL)
-                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+                .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
                 .dst_subpass(0)
-                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
+                .dst_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                )
                 .build()
         ];
 
@@ -221,127 +823,97 @@ impl VulkanRenderer {
         Ok(render_pass)
     }
     
-    fn get_mem_properties(device: &RendererDevice, instance: &ash::Instance) -> vk::PhysicalDeviceMemoryProperties {
-        unsafe {
-            instance
-                .get_physical_device_memory_properties(device.physical_device)
-        }
-    }
-
-    fn find_memory_type(
-        requirements: vk::MemoryRequirements,
-        mem_properties: vk::PhysicalDeviceMemoryProperties,
-        required_properties: vk::MemoryPropertyFlags,
-    ) -> u32 {
-        for i in 0..mem_properties.memory_type_count {
-            if requirements.memory_type_bits & (1 << i) != 0
-                && mem_properties.memory_types[i as usize]
-                    .property_flags
-                    .contains(required_properties)
-            {
-                return i;
-            }
-        }
-        panic!("Failed to find suitable memory type.")
-    }
-
-    fn create_buffer(
-        instance: &ash::Instance,
-        device: &RendererDevice,
-        size: vk::DeviceSize,
-        usage: vk::BufferUsageFlags,
-        mem_properties: vk::MemoryPropertyFlags,
-    ) -> (vk::Buffer, vk::DeviceMemory, vk::DeviceSize) {
-        let buffer = {
-            let buffer_info = vk::BufferCreateInfo::builder()
-                .size(size)
-                .usage(usage)
-                .sharing_mode(vk::SharingMode::EXCLUSIVE)
-                .build();
-            unsafe { device.logical_device.create_buffer(&buffer_info, None).unwrap() }
-        };
-
-        let mem_requirements = unsafe { device.logical_device.get_buffer_memory_requirements(buffer) };
-        let memory = {
-            let mem_type = Self::find_memory_type(
-                mem_requirements,
-                Self::get_mem_properties(device, instance),
-                mem_properties,
-            );
-
-            let alloc_info = vk::MemoryAllocateInfo::builder()
-                .allocation_size(mem_requirements.size)
-                .memory_type_index(mem_type)
-                .build();
-            unsafe { device.logical_device.allocate_memory(&alloc_info, None).unwrap() }
-        };
-
-        unsafe { device.logical_device.bind_buffer_memory(buffer, memory, 0).unwrap() };
-
-        (buffer, memory, mem_requirements.size)
-
-    }
-
-    fn create_device_local_buffer_with_data<A, T: Copy>(
-        instance: &ash::Instance,
+    /// Copies `data` into a new device local buffer on the transfer queue. When that queue
+    /// belongs to another family than graphics, the buffer's ownership is then moved to the
+    /// graphics family, which draws from it.
+    pub(crate) fn create_device_local_buffer_with_data<T: Copy>(
         device: &RendererDevice,
-        command_pool: vk::CommandPool,
-        transfer_queue: vk::Queue,
+        command_pools: &CommandPools,
+        name: &str,
         usage: vk::BufferUsageFlags,
         data: &[T],
-    ) -> (vk::Buffer, vk::DeviceMemory) {
-        let l_device = &device.logical_device;
-        let size = (data.len() * std::mem::size_of::<T>()) as vk::DeviceSize;
-        let (staging_buffer, staging_memory, staging_mem_size) = Self::create_buffer(
-            instance,
+    ) -> Result<AllocatedBuffer> {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+        let mut staging_buffer = AllocatedBuffer::new(
             device,
+            &format!("{} (staging)", name),
             size,
             vk::BufferUsageFlags::TRANSFER_SRC,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-        );
+            MemoryLocation::CpuToGpu,
+        )?;
 
-        unsafe {
-            let data_ptr = l_device
-                .map_memory(staging_memory, 0, size, vk::MemoryMapFlags::empty())
-                .unwrap();
-            let mut align = ash::util::Align::new(data_ptr, std::mem::align_of::<A>() as _, staging_mem_size);
-            align.copy_from_slice(data);
-            l_device.unmap_memory(staging_memory);
-        };
+        let uploaded = staging_buffer.write(data).and_then(|_| {
+            let mut buffer = AllocatedBuffer::new(
+                device,
+                name,
+                size,
+                vk::BufferUsageFlags::TRANSFER_DST | usage,
+                MemoryLocation::GpuOnly,
+            )?;
+
+            match Self::copy_buffer(device, command_pools, staging_buffer.buffer, buffer.buffer, size, usage) {
+                Ok(()) => Ok(buffer),
+                Err(error) => {
+                    unsafe { buffer.destroy(device) };
+                    Err(error)
+                }
+            }
+        });
 
-        let (buffer, memory, _) = Self::create_buffer(
-            instance,
-            device,
-            size,
-            vk::BufferUsageFlags::TRANSFER_DST | usage,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-        );
+        unsafe { staging_buffer.destroy(device) };
 
-        Self::copy_buffer(
-            device,
-            command_pool,
-            transfer_queue,
-            staging_buffer,
-            buffer,
-            size,
-        );
+        uploaded
+    }
 
-        unsafe {
-            l_device.destroy_buffer(staging_buffer, None);
-            l_device.free_memory(staging_memory, None);
-        };
+    /// How the graphics queue reads a buffer of the given usage, to wait on before the first use.
+    fn buffer_read_access(usage: vk::BufferUsageFlags) -> (vk::AccessFlags, vk::PipelineStageFlags) {
+        let mut access = vk::AccessFlags::empty();
+        let mut stages = vk::PipelineStageFlags::empty();
 
-        (buffer, memory)
+        if usage.contains(vk::BufferUsageFlags::VERTEX_BUFFER) {
+            access |= vk::AccessFlags::VERTEX_ATTRIBUTE_READ;
+            stages |= vk::PipelineStageFlags::VERTEX_INPUT;
+        }
+        if usage.contains(vk::BufferUsageFlags::INDEX_BUFFER) {
+            access |= vk::AccessFlags::INDEX_READ;
+            stages |= vk::PipelineStageFlags::VERTEX_INPUT;
+        }
+        if usage.contains(vk::BufferUsageFlags::UNIFORM_BUFFER) {
+            access |= vk::AccessFlags::UNIFORM_READ;
+            stages |= vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER;
+        }
+
+        match stages.is_empty() {
+            true => (vk::AccessFlags::MEMORY_READ, vk::PipelineStageFlags::ALL_COMMANDS),
+            false => (access, stages),
+        }
     }
+
     fn copy_buffer(
         device: &RendererDevice,
-        command_pool: vk::CommandPool,
-        transfer_queue: vk::Queue,
+        command_pools: &CommandPools,
         src: vk::Buffer,
         dst: vk::Buffer,
         size: vk::DeviceSize,
-    ) {
-        Self::execute_one_time_commands(device, command_pool, transfer_queue, |buffer| {
+        usage: vk::BufferUsageFlags,
+    ) -> Result<()> {
+        let families = device.family_indices;
+        let separate = device.has_separate_transfer();
+
+        // same barrier on both sides, the release half on the transfer queue and the acquire half on graphics
+        let ownership_barrier = |src_access, dst_access| {
+            vk::BufferMemoryBarrier::builder()
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(families.transfer)
+                .dst_queue_family_index(families.graphics)
+                .buffer(dst)
+                .offset(0)
+                .size(vk::WHOLE_SIZE)
+                .build()
+        };
+
+        Self::execute_one_time_commands(device, command_pools.transfer, device.transfer_queue, |buffer| {
             let region = vk::BufferCopy {
                 src_offset: 0,
                 dst_offset: 0,
@@ -350,15 +922,53 @@ impl VulkanRenderer {
             let regions = [region];
 
             unsafe { device.logical_device.cmd_copy_buffer(buffer, src, dst, &regions) };
-        });
+
+            if separate {
+                let barriers = [ownership_barrier(vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::empty())];
+                unsafe {
+                    device.logical_device.cmd_pipeline_barrier(
+                        buffer,
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &barriers,
+                        &[],
+                    )
+                };
+            }
+        })?;
+
+        if separate {
+            let (read_access, read_stages) = Self::buffer_read_access(usage);
+
+            Self::execute_one_time_commands(device, command_pools.graphics, device.graphics_queue, |buffer| {
+                let barriers = [ownership_barrier(vk::AccessFlags::empty(), read_access)];
+                unsafe {
+                    device.logical_device.cmd_pipeline_barrier(
+                        buffer,
+                        vk::PipelineStageFlags::TOP_OF_PIPE,
+                        read_stages,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &barriers,
+                        &[],
+                    )
+                };
+            })?;
+        }
+
+        Ok(())
     }
 
-    fn execute_one_time_commands<F: FnOnce(vk::CommandBuffer)>(
+    /// Records `executor` into a one-off command buffer, submits it to `queue` and waits on a
+    /// fence for it to finish, so other queues keep running meanwhile.
+    pub(crate) fn execute_one_time_commands<F: FnOnce(vk::CommandBuffer)>(
         device: &RendererDevice,
         command_pool: vk::CommandPool,
         queue: vk::Queue,
         executor: F,
-    ) {
+    ) -> Result<()> {
         let command_buffer = {
             let alloc_info = vk::CommandBufferAllocateInfo::builder()
                 .level(vk::CommandBufferLevel::PRIMARY)
@@ -366,157 +976,142 @@ impl VulkanRenderer {
                 .command_buffer_count(1)
                 .build();
 
-            unsafe { device.logical_device.allocate_command_buffers(&alloc_info).unwrap()[0] }
+            unsafe { device.logical_device.allocate_command_buffers(&alloc_info)?[0] }
         };
         let command_buffers = [command_buffer];
 
-        // Begin recording
-        {
+        let fence = match unsafe { device.logical_device.create_fence(&vk::FenceCreateInfo::default(), None) } {
+            Err(error) => {
+                unsafe { device.logical_device.free_command_buffers(command_pool, &command_buffers) };
+                return Err(error.into());
+            },
+            Ok(fence) => fence
+        };
+
+        let executed = unsafe {
+            // Begin recording
             let begin_info = vk::CommandBufferBeginInfo::builder()
                 .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
                 .build();
-            unsafe {
-                device
-                    .logical_device.begin_command_buffer(command_buffer, &begin_info)
-                    .unwrap()
-            };
-        }
 
-        // Execute user function
-        executor(command_buffer);
+            device.logical_device.begin_command_buffer(command_buffer, &begin_info)
+                .and_then(|_| {
+                    // Execute user function
+                    executor(command_buffer);
 
-        // End recording
-        unsafe { device.logical_device.end_command_buffer(command_buffer).unwrap() };
+                    device.logical_device.end_command_buffer(command_buffer)
+                })
+                .and_then(|_| {
+                    // Submit and wait
+                    let submit_info = vk::SubmitInfo::builder()
+                        .command_buffers(&command_buffers)
+                        .build();
 
-        // Submit and wait
-        {
-            let submit_info = vk::SubmitInfo::builder()
-                .command_buffers(&command_buffers)
-                .build();
-            let submit_infos = [submit_info];
-            unsafe {
-                device.logical_device.
-                    queue_submit(queue, &submit_infos, vk::Fence::null())
-                    .unwrap();
-                device.logical_device.queue_wait_idle(queue).unwrap();
-            };
-        }
+                    device.logical_device.queue_submit(queue, &[submit_info], fence)
+                })
+                .and_then(|_| device.logical_device.wait_for_fences(&[fence], true, u64::MAX))
+        };
+
+        // Free, whether it ran or not
+        unsafe {
+            device.logical_device.destroy_fence(fence, None);
+            device.logical_device.free_command_buffers(command_pool, &command_buffers);
+        };
 
-        // Free
-        unsafe { device.logical_device.free_command_buffers(command_pool, &command_buffers) };
+        Ok(executed?)
     }
 
 
+    fn record_frame(&self, image_index: u32, draw_list: &DrawList) -> Result<()> {
+        let device = &self.main_device.logical_device;
+        let extent = self.extent();
+        let framebuffer = self.framebuffers()[image_index as usize];
+        let frame_index = self.frames.current_frame;
+        let command_buffer = self.frames.current().command_buffer;
 
-    fn create_vertex_buffer(
-        device: &RendererDevice,
-        instance: &ash::Instance,
-        command_pool: vk::CommandPool,
-        transfer_queue: vk::Queue,
-        vertices: &[Vertex],
-    ) -> (vk::Buffer, vk::DeviceMemory) {
-        Self::create_device_local_buffer_with_data::<u32, _>(
-            instance,
-            device,
-            command_pool,
-            transfer_queue,
-            vk::BufferUsageFlags::VERTEX_BUFFER,
-            vertices,
-        )
-    }
-
-    fn create_index_buffer(
-        device: &RendererDevice,
-        instance: &ash::Instance,
-        command_pool: vk::CommandPool,
-        transfer_queue: vk::Queue,
-        indices: &[u32],
-    ) -> (vk::Buffer, vk::DeviceMemory) {
-        Self::create_device_local_buffer_with_data::<u16, _>(
-            instance,
-            device,
-            command_pool,
-            transfer_queue,
-            vk::BufferUsageFlags::INDEX_BUFFER,
-            indices,
-        )
-    }
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
 
-    fn get_memory_type_index(
-        device: &RendererDevice,
-        instance: &ash::Instance,
-        properties: vk::MemoryPropertyFlags,
-        requirements: vk::MemoryRequirements
-    ) -> Result<u32> {
-        let memory = unsafe {
-            instance.get_physical_device_memory_properties(device.physical_device)
+        unsafe {
+            device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
+            device.begin_command_buffer(command_buffer, &begin_info)?;
         };
-        (0..memory.memory_type_count)
-            .find(|i| {
-                let suitable = (requirements.memory_type_bits & (1 << i)) != 0;
-                let memory_type = memory.memory_types[*i as usize];
-                suitable && memory_type.property_flags.contains(properties)
-            })
-            .ok_or_else(|| panic!("Failed to find suitable memory type."))
-    }
 
-    fn fill_command_buffers(&self) -> Result<()> {
-        for (i, &command_buffer) in self.graphics_command_buffers.iter().enumerate() {
-            let begin_info = vk::CommandBufferBeginInfo::builder();
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: draw_list.clear_color,
+                }
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                }
+            },
+        ];
 
-            unsafe {
-                self.main_device.logical_device.begin_command_buffer(command_buffer, &begin_info).expect("Falha ao iniciar o buffer de comandos")
-            };
+        let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.render_pass)
+            .framebuffer(framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            })
+            .clear_values(&clear_values);
 
-            let clear_values = [
-                vk::ClearValue {
-                    color: vk::ClearColorValue {
-                        float32: [0.0, 0.0, 0.0, 1.0],
-                    }
-                },
-            ];
-            println!("Render pass: {:?}", self.render_pass);
-            println!("Framebuffer: {:?}", self.swapchain.framebuffers[i]);
-            println!("Render area: {:?}", vk::Rect2D {
-                            offset: vk::Offset2D { x: 0, y: 0 },
-                            extent: self.swapchain.extent,
-                        });
-            
-            let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
-                .render_pass(self.render_pass)
-                .framebuffer(self.swapchain.framebuffers[i])
-                .render_area(vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: self.swapchain.extent,
-                })
-                .clear_values(&clear_values);
+        unsafe {
+            device.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_begin_info,
+                vk::SubpassContents::INLINE,
+            );
+        };
 
-            println!("Initiate render pass");
+        let recorder = CommandRecorder::new(device, command_buffer, extent);
+        let mut pipeline = &self.pipelines[PipelineHandle::DEFAULT.0];
+        recorder.bind_pipeline(pipeline);
+        recorder.reset_viewport_and_scissor();
 
-            unsafe {
-                self.main_device.logical_device.cmd_begin_render_pass(
-                    command_buffer,
-                    &render_pass_begin_info,
-                    vk::SubpassContents::INLINE,
-                );
-
-                self.main_device.logical_device.cmd_bind_pipeline(
-                    command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    self.graphics_pipeline.pipeline,
-                );
-                
-                self.main_device.logical_device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer], &[0]);
-                self.main_device.logical_device.cmd_bind_index_buffer(command_buffer, self.index_buffer, 0, vk::IndexType::UINT32);
-
-                self.main_device.logical_device.cmd_draw(command_buffer, self.model_index_count as _, 1, 0, 0);
-
-                self.main_device.logical_device.cmd_end_render_pass(command_buffer);
-
-                self.main_device.logical_device.end_command_buffer(command_buffer)?;
-            };
+        for command in &draw_list.commands {
+            match *command {
+                DrawCommand::SetPipeline(handle) => {
+                    pipeline = &self.pipelines[handle.0];
+                    recorder.bind_pipeline(pipeline);
+                },
+                DrawCommand::SetViewport(rect) => recorder.set_viewport(rect),
+                DrawCommand::SetScissor(rect) => recorder.set_scissor(rect),
+                DrawCommand::ResetViewport => recorder.reset_viewport_and_scissor(),
+                DrawCommand::DrawMesh { mesh, texture, transform, color } => {
+                    let mesh = &self.meshes[mesh.0];
+                    let push = PushConstants { model: transform, color };
+
+                    recorder.bind_descriptor_set(pipeline, self.descriptors.set(texture.0, frame_index));
+                    recorder.push_constants(pipeline, PushConstants::range().stage_flags, &push);
+                    recorder.bind_vertex_buffer(&mesh.vertex_buffer);
+                    recorder.bind_index_buffer(&mesh.index_buffer, mesh.index_type);
+                    recorder.draw_indexed(mesh.index_count, 0, 0);
+                },
+                DrawCommand::DrawSprites { texture, first_vertex, vertex_count, transform, color } => {
+                    let buffer = match self.sprite_buffers.get(frame_index) {
+                        None => continue,
+                        Some(buffer) => buffer
+                    };
+                    let push = PushConstants { model: transform, color };
+
+                    recorder.bind_descriptor_set(pipeline, self.descriptors.set(texture.0, frame_index));
+                    recorder.push_constants(pipeline, PushConstants::range().stage_flags, &push);
+                    recorder.bind_vertex_buffer(buffer);
+                    recorder.draw(vertex_count, first_vertex);
+                },
+            }
         }
 
+        unsafe {
+            device.cmd_end_render_pass(command_buffer);
+            device.end_command_buffer(command_buffer)?;
+        };
+
         Ok(())
     }
 }
@@ -525,15 +1120,31 @@ impl Drop for VulkanRenderer {
     fn drop(&mut self) {
         unsafe {
             self.main_device.logical_device.device_wait_idle().unwrap();
+            self.frames.cleanup(&self.main_device, self.command_pools.graphics);
             self.command_pools.cleanup(&self.main_device);
-            self.graphics_pipeline.cleanup(&self.main_device.logical_device);
+            for pipeline in &self.pipelines {
+                pipeline.cleanup(&self.main_device.logical_device);
+            }
+            self.descriptors.cleanup(&self.main_device);
+            self.uniform_buffers.cleanup(&self.main_device);
+            for texture in &mut self.textures {
+                texture.cleanup(&self.main_device);
+            }
             self.main_device.logical_device.destroy_render_pass(self.render_pass, None);
-            self.main_device.logical_device.destroy_buffer(self.index_buffer, None);
-            self.main_device.logical_device.destroy_buffer(self.vertex_buffer, None);
+            for mesh in &mut self.meshes {
+                mesh.cleanup(&self.main_device);
+            }
+            self.sprite_buffers.cleanup(&self.main_device);
             self.debug.cleanup();
-            self.swapchain.cleanup(&self.main_device);
-            self.main_device.logical_device.destroy_buffer(self.vertex_buffer, None);
-            self.window.cleanup();
+            if let Some(swapchain) = &mut self.swapchain {
+                swapchain.cleanup(&self.main_device);
+            }
+            if let Some(offscreen) = &mut self.offscreen {
+                offscreen.cleanup(&self.main_device);
+            }
+            if let Some(window) = &self.window {
+                window.cleanup();
+            }
             self.main_device.cleanup();
             self.instance.destroy_instance(None);
         }
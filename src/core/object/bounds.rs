@@ -0,0 +1,44 @@
+use super::vertex::Vertex;
+
+/// Axis aligned bounding box in model space, plus the bounding sphere around its center.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    /// Distance from `center()` to the farthest vertex.
+    pub radius: f32,
+}
+
+impl Bounds {
+    /// Zero sized box at the origin when there are no vertices.
+    pub fn from_vertices(vertices: &[Vertex]) -> Self {
+        let first = match vertices.first() {
+            None => return Self { min: [0.0; 3], max: [0.0; 3], radius: 0.0 },
+            Some(vertex) => vertex.pos
+        };
+
+        let mut bounds = Self { min: first, max: first, radius: 0.0 };
+        for vertex in vertices {
+            for axis in 0..3 {
+                bounds.min[axis] = bounds.min[axis].min(vertex.pos[axis]);
+                bounds.max[axis] = bounds.max[axis].max(vertex.pos[axis]);
+            }
+        }
+
+        let center = bounds.center();
+        let radius2 = vertices.iter()
+            .map(|vertex| (0..3).map(|axis| (vertex.pos[axis] - center[axis]).powi(2)).sum::<f32>())
+            .fold(0.0, f32::max);
+        bounds.radius = radius2.sqrt();
+
+        bounds
+    }
+
+    pub fn center(&self) -> [f32; 3] {
+        [
+            (self.min[0] + self.max[0]) / 2.0,
+            (self.min[1] + self.max[1]) / 2.0,
+            (self.min[2] + self.max[2]) / 2.0,
+        ]
+    }
+}
@@ -0,0 +1,496 @@
+use super::bounds::Bounds;
+use super::error::ModelError;
+use super::material::{Material, TextureSource};
+use super::model::{IndexFormat, Model, SubMesh};
+use super::optimize::OptimizeOptions;
+use super::vertex::Vertex;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// Layout of a .pmesh file, all integers little endian:
+//
+//   header:    MAGIC, VERSION: u32, size_of::<Vertex>: u32, optimizations: u8 (bit 0 deduplicate,
+//              bit 1 reorder, bit 2 short indices), source stamp,
+//              material file count: u32, then per material file its path and stamp,
+//              texture count: u32, material count: u32, mesh count: u32
+//   stamps:    exists: u8, then if it does length: u64, modification time in nanoseconds: u64
+//   textures:  kind: u8 (0 file, 1 rgba), then the path string or width, height and pixel bytes
+//   materials: name, diffuse color: 3 x f32, texture: i32 (-1 for none), alpha: f32
+//   meshes:    name, material: i32, bounds: 7 x f32 (min, max, radius), index format: u8
+//              (0 u32, 1 u16), vertex count: u32, index count: u32, vertex blob, index blob
+//
+// Strings and byte arrays are prefixed with their u32 length. The vertex blob is the in-memory
+// `Vertex` layout, so cache files are only meant for the machine that wrote them.
+
+pub const MAGIC: [u8; 8] = *b"PMMESH\0\0";
+/// Bumped whenever the layout above changes, invalidating every existing cache file.
+pub const VERSION: u32 = 4;
+pub const EXTENSION: &str = "pmesh";
+
+/// Where the cache of `source` lives: next to it, with `.pmesh` appended to the file name.
+pub fn cache_path(source: &Path) -> PathBuf {
+    let mut name = source.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(EXTENSION);
+    source.with_file_name(name)
+}
+
+/// Length and modification time of a file, stored in the header to detect changes.
+/// `None` when the file does not exist (or its metadata cannot be read).
+fn stamp(path: &Path) -> Option<(u64, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified.as_nanos() as u64))
+}
+
+/// The `OptimizeOptions` the cached model was optimized with, one bit per optimization.
+fn optimize_flags(options: &OptimizeOptions) -> u8 {
+    options.deduplicate as u8
+        | (options.reorder as u8) << 1
+        | (options.short_indices as u8) << 2
+}
+
+/// Reads the cached model of `source` by memory mapping its cache file. Returns `None` when there
+/// is no cache, or it was written by another version, with other `options`, from an older source
+/// file or older material files, or is corrupt.
+pub fn read(source: &Path, options: &OptimizeOptions) -> Option<Model> {
+    let source_stamp = stamp(source)?;
+    let file = File::open(cache_path(source)).ok()?;
+    let mapped = unsafe { memmap2::Mmap::map(&file).ok()? };
+
+    let mut reader = Reader { data: &mapped, offset: 0 };
+
+    let valid = reader.bytes(MAGIC.len())? == MAGIC
+        && reader.u32()? == VERSION
+        && reader.u32()? == std::mem::size_of::<Vertex>() as u32
+        && reader.u8()? == optimize_flags(options)
+        && reader.stamp()? == Some(source_stamp);
+
+    if !valid {
+        return None;
+    }
+
+    // a material file that appeared, changed or vanished since makes the cache stale as well
+    let material_file_count = reader.u32()?;
+    for _ in 0..material_file_count {
+        let path = PathBuf::from(reader.string()?);
+        if reader.stamp()? != stamp(&path) {
+            return None;
+        }
+    }
+
+    let texture_count = reader.u32()?;
+    let material_count = reader.u32()?;
+    let mesh_count = reader.u32()?;
+
+    // the counts are not trusted for preallocation, a corrupt one just runs out of bytes
+    let mut textures = vec![];
+    for _ in 0..texture_count {
+        let texture = match reader.u8()? {
+            0 => TextureSource::File(PathBuf::from(reader.string()?)),
+            1 => TextureSource::Rgba {
+                width: reader.u32()?,
+                height: reader.u32()?,
+                pixels: reader.blob()?.to_vec(),
+            },
+            _ => return None,
+        };
+        textures.push(texture);
+    }
+
+    let mut materials = vec![];
+    for _ in 0..material_count {
+        materials.push(Material {
+            name: reader.string()?,
+            diffuse_color: [reader.f32()?, reader.f32()?, reader.f32()?],
+            diffuse_texture: reader.index()?,
+            alpha: reader.f32()?,
+        });
+    }
+
+    let mut meshes = vec![];
+    for _ in 0..mesh_count {
+        let name = reader.string()?;
+        let material = reader.index()?;
+        let bounds = Bounds {
+            min: [reader.f32()?, reader.f32()?, reader.f32()?],
+            max: [reader.f32()?, reader.f32()?, reader.f32()?],
+            radius: reader.f32()?,
+        };
+        let index_format = match reader.u8()? {
+            0 => IndexFormat::U32,
+            1 => IndexFormat::U16,
+            _ => return None,
+        };
+        let vertex_count = reader.u32()? as usize;
+        let index_count = reader.u32()? as usize;
+
+        meshes.push(SubMesh {
+            name,
+            vertices: reader.array::<Vertex>(vertex_count)?,
+            indices: reader.array::<u32>(index_count)?,
+            material,
+            bounds,
+            index_format,
+        });
+    }
+
+    Some(Model {
+        meshes,
+        materials,
+        textures,
+    })
+}
+
+/// Writes the cache of `source`, replacing any previous one, for `model` optimized with `options`.
+/// `material_files` are the MTL files the model was loaded with, missing ones included, whose
+/// changes invalidate the cache too.
+///
+/// The cache is written to a temporary file first and renamed over the old one, so readers
+/// never map a half written cache.
+pub fn write(
+    source: &Path,
+    material_files: &[PathBuf],
+    options: &OptimizeOptions,
+    model: &Model,
+) -> Result<(), ModelError> {
+    let source_stamp = match stamp(source) {
+        None => return Err(ModelError::NotFound(source.to_path_buf())),
+        Some(stamp) => stamp
+    };
+
+    let path = cache_path(source);
+    let mut temporary = path.clone().into_os_string();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let io_error = |error| ModelError::Io(path.clone(), error);
+
+    let mut writer = Writer { out: BufWriter::new(File::create(&temporary).map_err(io_error)?) };
+
+    let written = (|| {
+        writer.bytes(&MAGIC)?;
+        writer.u32(VERSION)?;
+        writer.u32(std::mem::size_of::<Vertex>() as u32)?;
+        writer.u8(optimize_flags(options))?;
+        writer.stamp(Some(source_stamp))?;
+
+        writer.u32(material_files.len() as u32)?;
+        for material_file in material_files {
+            writer.string(&material_file.to_string_lossy())?;
+            writer.stamp(stamp(material_file))?;
+        }
+
+        writer.u32(model.textures.len() as u32)?;
+        writer.u32(model.materials.len() as u32)?;
+        writer.u32(model.meshes.len() as u32)?;
+
+        for texture in &model.textures {
+            match texture {
+                TextureSource::File(path) => {
+                    writer.u8(0)?;
+                    writer.string(&path.to_string_lossy())?;
+                },
+                TextureSource::Rgba { width, height, pixels } => {
+                    writer.u8(1)?;
+                    writer.u32(*width)?;
+                    writer.u32(*height)?;
+                    writer.blob(pixels)?;
+                },
+            }
+        }
+
+        for material in &model.materials {
+            writer.string(&material.name)?;
+            for channel in material.diffuse_color {
+                writer.f32(channel)?;
+            }
+            writer.index(material.diffuse_texture)?;
+            writer.f32(material.alpha)?;
+        }
+
+        for mesh in &model.meshes {
+            writer.string(&mesh.name)?;
+            writer.index(mesh.material)?;
+            for value in mesh.bounds.min.iter().chain(mesh.bounds.max.iter()) {
+                writer.f32(*value)?;
+            }
+            writer.f32(mesh.bounds.radius)?;
+            writer.u8(match mesh.index_format {
+                IndexFormat::U32 => 0,
+                IndexFormat::U16 => 1,
+            })?;
+            writer.u32(mesh.vertices.len() as u32)?;
+            writer.u32(mesh.indices.len() as u32)?;
+            writer.array(&mesh.vertices)?;
+            writer.array(&mesh.indices)?;
+        }
+
+        writer.out.flush()
+    })();
+
+    // closed before the rename, which some platforms refuse on open files
+    drop(writer);
+
+    let renamed = written.and_then(|_| std::fs::rename(&temporary, &path));
+    if renamed.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+
+    renamed.map_err(io_error)
+}
+
+struct Writer {
+    out: BufWriter<File>,
+}
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.out.write_all(bytes)
+    }
+
+    fn u8(&mut self, value: u8) -> std::io::Result<()> {
+        self.bytes(&[value])
+    }
+
+    fn u32(&mut self, value: u32) -> std::io::Result<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn u64(&mut self, value: u64) -> std::io::Result<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn f32(&mut self, value: f32) -> std::io::Result<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn stamp(&mut self, stamp: Option<(u64, u64)>) -> std::io::Result<()> {
+        match stamp {
+            None => self.u8(0),
+            Some((len, modified)) => {
+                self.u8(1)?;
+                self.u64(len)?;
+                self.u64(modified)
+            },
+        }
+    }
+
+    fn index(&mut self, index: Option<usize>) -> std::io::Result<()> {
+        let value = match index {
+            None => -1,
+            Some(index) => index as i32,
+        };
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn blob(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.u32(bytes.len() as u32)?;
+        self.bytes(bytes)
+    }
+
+    fn string(&mut self, value: &str) -> std::io::Result<()> {
+        self.blob(value.as_bytes())
+    }
+
+    fn array<T: Copy>(&mut self, values: &[T]) -> std::io::Result<()> {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(values.as_ptr() as *const u8, std::mem::size_of_val(values))
+        };
+        self.bytes(bytes)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.offset..self.offset.checked_add(len)?)?;
+        self.offset += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn stamp(&mut self) -> Option<Option<(u64, u64)>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => Some(Some((self.u64()?, self.u64()?))),
+            _ => None,
+        }
+    }
+
+    fn index(&mut self) -> Option<Option<usize>> {
+        let value = i32::from_le_bytes(self.bytes(4)?.try_into().ok()?);
+        match value {
+            -1 => Some(None),
+            value if value >= 0 => Some(Some(value as usize)),
+            _ => None,
+        }
+    }
+
+    fn blob(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.blob()?.to_vec()).ok()
+    }
+
+    /// Copies `count` plain values out of the (possibly unaligned) mapped bytes.
+    fn array<T: Copy>(&mut self, count: usize) -> Option<Vec<T>> {
+        let bytes = self.bytes(count.checked_mul(std::mem::size_of::<T>())?)?;
+        let mut values = Vec::with_capacity(count);
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), values.as_mut_ptr() as *mut u8, bytes.len());
+            values.set_len(count);
+        }
+
+        Some(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory of its own for each test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pencilmake-cache-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn model() -> Model {
+        let vertices = vec![
+            Vertex::new([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0]),
+            Vertex::new([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0]),
+            Vertex::new([0.0, 2.0, 0.5], [0.0, 0.0, 1.0], [0.0, 1.0]),
+        ];
+
+        Model {
+            meshes: vec![SubMesh {
+                name: "triangle".to_string(),
+                bounds: Bounds::from_vertices(&vertices),
+                vertices,
+                indices: vec![0, 1, 2],
+                material: Some(0),
+                index_format: IndexFormat::U16,
+            }],
+            materials: vec![Material {
+                name: "red".to_string(),
+                diffuse_color: [1.0, 0.5, 0.25],
+                diffuse_texture: Some(1),
+                alpha: 0.75,
+            }],
+            textures: vec![
+                TextureSource::File(PathBuf::from("textures/duck.png")),
+                TextureSource::Rgba { width: 1, height: 2, pixels: vec![1, 2, 3, 4, 5, 6, 7, 8] },
+            ],
+        }
+    }
+
+    #[test]
+    fn read_returns_what_write_wrote() {
+        let dir = test_dir("round-trip");
+        let source = dir.join("model.obj");
+        let mtl = dir.join("model.mtl");
+        std::fs::write(&source, "o triangle").unwrap();
+        std::fs::write(&mtl, "newmtl red").unwrap();
+
+        let options = OptimizeOptions::default();
+        let written = model();
+        write(&source, &[mtl], &options, &written).unwrap();
+        assert!(!dir.join("model.obj.pmesh.tmp").exists());
+
+        let cached = read(&source, &options).expect("cache not read back");
+
+        let (mesh, expected) = (&cached.meshes[0], &written.meshes[0]);
+        assert_eq!(cached.meshes.len(), 1);
+        assert_eq!(mesh.name, expected.name);
+        assert_eq!(mesh.indices, expected.indices);
+        assert_eq!(mesh.material, expected.material);
+        assert_eq!(mesh.index_format, expected.index_format);
+        assert_eq!(mesh.bounds.min, expected.bounds.min);
+        assert_eq!(mesh.bounds.max, expected.bounds.max);
+        assert_eq!(mesh.bounds.radius, expected.bounds.radius);
+
+        assert_eq!(mesh.vertices.len(), expected.vertices.len());
+        for (vertex, expected) in mesh.vertices.iter().zip(&expected.vertices) {
+            assert_eq!(vertex.pos, expected.pos);
+            assert_eq!(vertex.color, expected.color);
+            assert_eq!(vertex.coords, expected.coords);
+            assert_eq!(vertex.normal, expected.normal);
+        }
+
+        let (material, expected) = (&cached.materials[0], &written.materials[0]);
+        assert_eq!(cached.materials.len(), 1);
+        assert_eq!(material.name, expected.name);
+        assert_eq!(material.diffuse_color, expected.diffuse_color);
+        assert_eq!(material.diffuse_texture, expected.diffuse_texture);
+        assert_eq!(material.alpha, expected.alpha);
+
+        assert!(matches!(&cached.textures[..], [
+            TextureSource::File(path),
+            TextureSource::Rgba { width: 1, height: 2, pixels },
+        ] if path == Path::new("textures/duck.png") && pixels == &[1, 2, 3, 4, 5, 6, 7, 8]));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn changed_files_make_the_cache_stale() {
+        let dir = test_dir("stale");
+        let source = dir.join("model.obj");
+        let mtl = dir.join("model.mtl");
+        let missing_mtl = dir.join("missing.mtl");
+        std::fs::write(&source, "o triangle").unwrap();
+        std::fs::write(&mtl, "newmtl red").unwrap();
+
+        let options = OptimizeOptions::default();
+        let material_files = [mtl.clone(), missing_mtl.clone()];
+        write(&source, &material_files, &options, &model()).unwrap();
+        assert!(read(&source, &options).is_some());
+
+        std::fs::write(&mtl, "newmtl red\nKd 1 0 0").unwrap();
+        assert!(read(&source, &options).is_none(), "changed MTL file");
+
+        write(&source, &material_files, &options, &model()).unwrap();
+        std::fs::write(&missing_mtl, "newmtl blue").unwrap();
+        assert!(read(&source, &options).is_none(), "MTL file that appeared");
+
+        write(&source, &material_files, &options, &model()).unwrap();
+        std::fs::write(&source, "o triangle\nv 0 0 0").unwrap();
+        assert!(read(&source, &options).is_none(), "changed OBJ file");
+
+        write(&source, &material_files, &options, &model()).unwrap();
+        let unoptimized = OptimizeOptions { reorder: false, ..options };
+        assert!(read(&source, &unoptimized).is_none(), "other optimizations");
+        assert!(read(&source, &options).is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
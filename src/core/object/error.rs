@@ -0,0 +1,48 @@
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum ModelError {
+    NotFound(PathBuf),
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, tobj::LoadError),
+    Gltf(PathBuf, gltf::Error),
+    /// A mesh lacks data that has no sensible default, such as positions.
+    MissingAttribute {
+        mesh: String,
+        attribute: &'static str,
+    },
+    /// A glTF primitive that is not a triangle list.
+    UnsupportedPrimitive {
+        mesh: String,
+        mode: gltf::mesh::Mode,
+    },
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::NotFound(path) => write!(f, "model file {:?} not found", path),
+            ModelError::Io(path, error) => write!(f, "could not read {:?}: {}", path, error),
+            ModelError::Parse(path, error) => write!(f, "could not parse {:?}: {}", path, error),
+            ModelError::Gltf(path, error) => write!(f, "could not import {:?}: {}", path, error),
+            ModelError::MissingAttribute { mesh, attribute } => {
+                write!(f, "mesh {:?} has no {}", mesh, attribute)
+            },
+            ModelError::UnsupportedPrimitive { mesh, mode } => {
+                write!(f, "mesh {:?} is made of unsupported {:?} primitives", mesh, mode)
+            },
+        }
+    }
+}
+
+impl std::error::Error for ModelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ModelError::Io(_, error) => Some(error),
+            ModelError::Parse(_, error) => Some(error),
+            ModelError::Gltf(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
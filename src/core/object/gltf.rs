@@ -0,0 +1,217 @@
+use super::bounds::Bounds;
+use super::error::ModelError;
+use super::material::{Material, TextureSource};
+use super::model::{compute_normals, resolve_path, IndexFormat, Model, SubMesh};
+use super::vertex::Vertex;
+
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+use std::path::Path;
+
+/// Loads a glTF 2.0 file, either `.gltf` with external or embedded buffers or binary `.glb`,
+/// into the same `Model` as `load_model`. Node transforms are baked into the vertices, every
+/// triangle primitive becomes a submesh and the PBR base color becomes the diffuse material.
+/// Other primitive modes (points, lines, strips) fail the load.
+pub fn load_gltf<P: AsRef<Path>>(path: P) -> Result<Model, ModelError> {
+    let path = path.as_ref();
+    let fullpath = match resolve_path(path) {
+        None => return Err(ModelError::NotFound(path.to_path_buf())),
+        Some(fullpath) => fullpath
+    };
+
+    let (document, buffers, images) = match gltf::import(&fullpath) {
+        Err(error) => return Err(ModelError::Gltf(fullpath, error)),
+        Ok(imported) => imported
+    };
+
+    let mut meshes = vec![];
+    match document.default_scene().or_else(|| document.scenes().next()) {
+        Some(scene) => {
+            for node in scene.nodes() {
+                load_node(&node, Matrix4::identity(), &buffers, &mut meshes)?;
+            }
+        },
+        None => {
+            // no scene to place them, so take the meshes as they are
+            for mesh in document.meshes() {
+                load_mesh(&mesh, Matrix4::identity(), &buffers, &mut meshes)?;
+            }
+        }
+    }
+
+    let materials = document.materials()
+        .map(|material| {
+            let pbr = material.pbr_metallic_roughness();
+            let [r, g, b, a] = pbr.base_color_factor();
+
+            Material {
+                name: material.name().unwrap_or_default().to_string(),
+                diffuse_color: [r, g, b],
+                diffuse_texture: pbr.base_color_texture().map(|info| info.texture().source().index()),
+                alpha: a,
+            }
+        })
+        .collect();
+
+    // in image order, so the texture source indices above stay valid
+    let textures = images.iter()
+        .map(|image| TextureSource::Rgba {
+            width: image.width,
+            height: image.height,
+            pixels: to_rgba(image),
+        })
+        .collect();
+
+    Ok(Model {
+        meshes,
+        materials,
+        textures,
+    })
+}
+
+fn load_node(
+    node: &gltf::Node,
+    parent: Matrix4<f32>,
+    buffers: &[gltf::buffer::Data],
+    meshes: &mut Vec<SubMesh>,
+) -> Result<(), ModelError> {
+    let transform = parent * Matrix4::from(node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        load_mesh(&mesh, transform, buffers, meshes)?;
+    }
+
+    for child in node.children() {
+        load_node(&child, transform, buffers, meshes)?;
+    }
+
+    Ok(())
+}
+
+fn load_mesh(
+    mesh: &gltf::Mesh,
+    transform: Matrix4<f32>,
+    buffers: &[gltf::buffer::Data],
+    meshes: &mut Vec<SubMesh>,
+) -> Result<(), ModelError> {
+    let mesh_name = mesh.name().unwrap_or("mesh");
+
+    // normals follow the inverse transpose, so non-uniform scales keep them perpendicular
+    let linear = Matrix3::from_cols(transform.x.truncate(), transform.y.truncate(), transform.z.truncate());
+    let normal_matrix = linear.invert().unwrap_or(linear).transpose();
+
+    for (i, primitive) in mesh.primitives().enumerate() {
+        let name = format!("{}.{}", mesh_name, i);
+
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            return Err(ModelError::UnsupportedPrimitive {
+                mesh: name,
+                mode: primitive.mode(),
+            });
+        }
+
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+        let positions: Vec<[f32; 3]> = match reader.read_positions() {
+            None => {
+                return Err(ModelError::MissingAttribute {
+                    mesh: name,
+                    attribute: "positions",
+                })
+            },
+            Some(positions) => positions.collect()
+        };
+
+        let colors: Vec<[f32; 3]> = reader.read_colors(0)
+            .map(|colors| colors.into_rgb_f32().collect())
+            .unwrap_or_default();
+        let coords: Vec<[f32; 2]> = reader.read_tex_coords(0)
+            .map(|coords| coords.into_f32().collect())
+            .unwrap_or_default();
+        let normals: Vec<[f32; 3]> = reader.read_normals()
+            .map(|normals| normals.collect())
+            .unwrap_or_default();
+
+        let indices: Vec<u32> = match reader.read_indices() {
+            None => (0..positions.len() as u32).collect(),
+            Some(indices) => indices.into_u32().collect()
+        };
+
+        let mut vertices: Vec<Vertex> = positions.iter()
+            .enumerate()
+            .map(|(v, position)| {
+                let pos = transform.transform_point(Point3::from(*position));
+                let normal = match normals.get(v) {
+                    None => Vector3::new(0.0, 0.0, 0.0),
+                    Some(normal) => (normal_matrix * Vector3::from(*normal)).normalize(),
+                };
+
+                Vertex {
+                    pos: [pos.x, pos.y, pos.z],
+                    color: colors.get(v).copied().unwrap_or([1.0, 1.0, 1.0]),
+                    coords: coords.get(v).copied().unwrap_or([0.0, 0.0]),
+                    normal: normal.into(),
+                }
+            })
+            .collect();
+
+        if normals.is_empty() {
+            compute_normals(&mut vertices, &indices);
+        }
+
+        meshes.push(SubMesh {
+            name,
+            bounds: Bounds::from_vertices(&vertices),
+            vertices,
+            indices,
+            material: primitive.material().index(),
+            index_format: IndexFormat::U32,
+        });
+    }
+
+    Ok(())
+}
+
+/// Expands any glTF image format to RGBA8, as `Texture::from_rgba` expects.
+fn to_rgba(image: &gltf::image::Data) -> Vec<u8> {
+    use gltf::image::Format;
+
+    let (channels, channel_size) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    let channel = |pixel: &[u8], c: usize| -> u8 {
+        let bytes = &pixel[c * channel_size..(c + 1) * channel_size];
+        match channel_size {
+            1 => bytes[0],
+            // little endian, keep the most significant byte
+            2 => bytes[1],
+            _ => (f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).clamp(0.0, 1.0) * 255.0) as u8,
+        }
+    };
+
+    let mut rgba = Vec::with_capacity((image.width * image.height * 4) as usize);
+    for pixel in image.pixels.chunks_exact(channels * channel_size) {
+        let value = match channels {
+            1 => {
+                let luminance = channel(pixel, 0);
+                [luminance, luminance, luminance, 255]
+            },
+            2 => [channel(pixel, 0), channel(pixel, 1), 0, 255],
+            3 => [channel(pixel, 0), channel(pixel, 1), channel(pixel, 2), 255],
+            _ => [channel(pixel, 0), channel(pixel, 1), channel(pixel, 2), channel(pixel, 3)],
+        };
+
+        rgba.extend_from_slice(&value);
+    }
+
+    rgba
+}
@@ -0,0 +1,138 @@
+use ash::vk;
+
+/// A type that can be a vertex attribute, with the format the shader reads it as.
+pub trait VertexAttribute {
+    const FORMAT: vk::Format;
+}
+
+macro_rules! impl_vertex_attribute {
+    ($($ty:ty => $format:ident),* $(,)?) => {
+        $(
+            impl VertexAttribute for $ty {
+                const FORMAT: vk::Format = vk::Format::$format;
+            }
+        )*
+    };
+}
+
+impl_vertex_attribute! {
+    f32 => R32_SFLOAT,
+    [f32; 2] => R32G32_SFLOAT,
+    [f32; 3] => R32G32B32_SFLOAT,
+    [f32; 4] => R32G32B32A32_SFLOAT,
+    u32 => R32_UINT,
+    [u32; 2] => R32G32_UINT,
+    [u32; 3] => R32G32B32_UINT,
+    [u32; 4] => R32G32B32A32_UINT,
+    i32 => R32_SINT,
+    [i32; 2] => R32G32_SINT,
+    [i32; 3] => R32G32B32_SINT,
+    [i32; 4] => R32G32B32A32_SINT,
+    // normalized to 0..1, e.g. packed colors
+    [u8; 4] => R8G8B8A8_UNORM,
+}
+
+/// Vertex input description of a vertex struct, normally generated with `vertex_layout!`.
+pub trait VertexLayout: Copy {
+    fn binding_description(binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }
+    }
+
+    /// One attribute per field, at locations 0, 1, 2... in declaration order.
+    fn attribute_descriptions(binding: u32) -> Vec<vk::VertexInputAttributeDescription>;
+}
+
+/// Declares a `#[repr(C)]` vertex struct and implements `VertexLayout` for it, taking the
+/// formats from the field types and the offsets from the compiler, so nothing goes stale
+/// when fields change. Field order is the shader location order.
+///
+/// ```ignore
+/// vertex_layout! {
+///     #[derive(Clone, Copy, Debug)]
+///     pub struct UiVertex {
+///         pub pos: [f32; 2],
+///         pub color: [u8; 4],
+///     }
+/// }
+/// ```
+macro_rules! vertex_layout {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident : $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(C)]
+        $vis struct $name {
+            $(
+                $(#[$field_meta])*
+                $field_vis $field: $ty,
+            )*
+        }
+
+        impl $crate::core::object::layout::VertexLayout for $name {
+            fn attribute_descriptions(binding: u32) -> Vec<ash::vk::VertexInputAttributeDescription> {
+                let fields = [
+                    $((
+                        <$ty as $crate::core::object::layout::VertexAttribute>::FORMAT,
+                        std::mem::offset_of!($name, $field) as u32,
+                    )),*
+                ];
+
+                fields.iter()
+                    .enumerate()
+                    .map(|(location, &(format, offset))| ash::vk::VertexInputAttributeDescription {
+                        location: location as u32,
+                        binding,
+                        format,
+                        offset,
+                    })
+                    .collect()
+            }
+        }
+    };
+}
+
+pub(crate) use vertex_layout;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::object::vertex::Vertex;
+
+    use std::mem::offset_of;
+
+    #[test]
+    fn vertex_attributes_match_the_struct() {
+        let attributes = Vertex::attribute_descriptions(1);
+
+        let expected = [
+            (offset_of!(Vertex, pos), vk::Format::R32G32B32_SFLOAT),
+            (offset_of!(Vertex, color), vk::Format::R32G32B32_SFLOAT),
+            (offset_of!(Vertex, coords), vk::Format::R32G32_SFLOAT),
+            (offset_of!(Vertex, normal), vk::Format::R32G32B32_SFLOAT),
+        ];
+        assert_eq!(expected.map(|(offset, _)| offset), [0, 12, 24, 32]);
+        assert_eq!(attributes.len(), expected.len());
+
+        for (location, (attribute, (offset, format))) in attributes.iter().zip(expected).enumerate() {
+            assert_eq!(attribute.location, location as u32);
+            assert_eq!(attribute.binding, 1);
+            assert_eq!(attribute.offset, offset as u32);
+            assert_eq!(attribute.format, format);
+        }
+
+        let binding = Vertex::binding_description(1);
+        assert_eq!(binding.binding, 1);
+        assert_eq!(binding.stride, 44);
+        assert_eq!(binding.input_rate, vk::VertexInputRate::VERTEX);
+    }
+}
@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+/// Where the pixels of a model texture come from.
+#[derive(Clone, Debug)]
+pub enum TextureSource {
+    /// Ready for `load_texture`.
+    File(PathBuf),
+    /// Decoded RGBA8 pixels, e.g. embedded in a GLB file.
+    Rgba {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+}
+
+/// The parts of a material the renderer uses.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    pub diffuse_color: [f32; 3],
+    /// Index into `Model::textures`.
+    pub diffuse_texture: Option<usize>,
+    pub alpha: f32,
+}
+
+impl Material {
+    pub fn from_mtl(material: &tobj::Material, diffuse_texture: Option<usize>) -> Self {
+        Self {
+            name: material.name.clone(),
+            diffuse_color: material.diffuse.unwrap_or([1.0, 1.0, 1.0]),
+            diffuse_texture,
+            alpha: material.dissolve.unwrap_or(1.0),
+        }
+    }
+
+    /// Diffuse color and alpha, as pushed to the fragment shader.
+    pub fn color(&self) -> [f32; 4] {
+        let [r, g, b] = self.diffuse_color;
+        [r, g, b, self.alpha]
+    }
+}
@@ -1,2 +1,9 @@
+pub mod bounds;
+pub mod cache;
+pub mod error;
+pub mod gltf;
+pub mod layout;
+pub mod material;
 pub mod model;
+pub mod optimize;
 pub mod vertex;
\ No newline at end of file
@@ -1,36 +1,211 @@
 
+use super::bounds::Bounds;
+use super::cache;
+use super::error::ModelError;
+use super::gltf::load_gltf;
+use super::material::{Material, TextureSource};
+use super::optimize::{optimize_model, OptimizeOptions};
 use super::vertex::Vertex;
+use cgmath::{InnerSpace, Vector3};
+use std::cell::RefCell;
 use std::io::Cursor;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-pub fn load<P: AsRef<Path>>(path: P) -> Cursor<Vec<u8>> {
-    use std::fs::File;
-    use std::io::Read;
+/// Finds `path` as given (absolute or relative to the working directory), falling back
+/// to the assets directory.
+pub fn resolve_path<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
+    let path = path.as_ref();
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+
+    let in_assets = Path::new("assets").join(path);
+    match in_assets.is_file() {
+        true => Some(in_assets),
+        false => None,
+    }
+}
 
-    let mut buf = Vec::new();
-    let fullpath = &Path::new("assets").join(&path);
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Cursor<Vec<u8>>, ModelError> {
+    let fullpath = match resolve_path(&path) {
+        None => return Err(ModelError::NotFound(path.as_ref().to_path_buf())),
+        Some(fullpath) => fullpath
+    };
     println!("Tentando acessar {:?}", fullpath);
-    let mut file = File::open(&fullpath).unwrap();
-    file.read_to_end(&mut buf).unwrap();
-    Cursor::new(buf)
+
+    match std::fs::read(&fullpath) {
+        Err(error) => Err(ModelError::Io(fullpath, error)),
+        Ok(buf) => Ok(Cursor::new(buf)),
+    }
+}
+
+/// Index size used on the GPU. Loaders keep every index as `u32`, `U16` only halves the
+/// uploaded index buffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexFormat {
+    /// Only valid while every index fits, i.e. for at most 65536 vertices.
+    U16,
+    #[default]
+    U32,
+}
+
+/// One mesh of an OBJ file, drawn with a single material.
+pub struct SubMesh {
+    pub name: String,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    /// Index into `Model::materials`, `None` when the mesh has no (known) material.
+    pub material: Option<usize>,
+    pub bounds: Bounds,
+    pub index_format: IndexFormat,
+}
+
+pub struct Model {
+    pub meshes: Vec<SubMesh>,
+    pub materials: Vec<Material>,
+    /// Shared by the materials, so each texture is uploaded once.
+    pub textures: Vec<TextureSource>,
+}
+
+/// Loads every mesh of an OBJ file together with its MTL materials, see `resolve_path` for
+/// where the file is looked up. A missing or broken MTL file only loses the materials, the
+/// geometry is still loaded. Absent texture coordinates default to 0 and absent colors to white.
+///
+/// The meshes are optimized with `options`, see `optimize_model`, and the result is cached in a
+/// `.pmesh` file next to the OBJ. It is read from there while neither the OBJ, its MTL files nor
+/// the options change.
+///
+/// `.gltf` and `.glb` files are loaded with `load_gltf` instead, without a cache.
+pub fn load_model<P: AsRef<Path>>(path: P, options: &OptimizeOptions) -> Result<Model, ModelError> {
+    let path = path.as_ref();
+    if is_gltf(path) {
+        let mut model = load_gltf(path)?;
+        optimize(path, &mut model, options);
+        return Ok(model);
+    }
+
+    let fullpath = match resolve_path(path) {
+        None => return Err(ModelError::NotFound(path.to_path_buf())),
+        Some(fullpath) => fullpath
+    };
+
+    if let Some(model) = cache::read(&fullpath, options) {
+        println!("Modelo {:?} carregado do cache", path);
+        return Ok(model);
+    }
+
+    let (mut model, material_files) = parse_obj(path)?;
+    optimize(path, &mut model, options);
+
+    if let Err(error) = cache::write(&fullpath, &material_files, options, &model) {
+        println!("Cache de {:?} não salvo: {}", path, error);
+    }
+
+    Ok(model)
 }
 
-pub fn load_model(dir: &str, name: &str) -> (Vec<Vertex>, Vec<u32>) {
-    let mut cursor = load(format!("{}/{}", dir, name));
-    let (models, _) = tobj::load_obj_buf(
+fn optimize(path: &Path, model: &mut Model, options: &OptimizeOptions) {
+    let stats = optimize_model(model, options);
+    print!("Modelo {:?} otimizado:\n{}", path, stats);
+}
+
+fn is_gltf(path: &Path) -> bool {
+    let extension = path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    matches!(extension.as_deref(), Some("gltf" | "glb"))
+}
+
+/// The model and the MTL files it references, whether they loaded or not.
+fn parse_obj(path: &Path) -> Result<(Model, Vec<PathBuf>), ModelError> {
+    let mut cursor = load(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let material_files = RefCell::new(vec![]);
+
+    let (models, materials) = tobj::load_obj_buf(
         &mut cursor,
         &tobj::LoadOptions {
             single_index: true,
             triangulate: true,
             ..Default::default()
         },
-        |_| Ok((vec![], ahash::AHashMap::new())),
-    ).unwrap();
+        |mtl_path| {
+            let mtl_path = dir.join(mtl_path);
+            material_files.borrow_mut().push(resolve_path(&mtl_path).unwrap_or_else(|| mtl_path.clone()));
 
+            match load(mtl_path) {
+                Err(_) => Err(tobj::LoadError::OpenFileFailed),
+                Ok(mut mtl) => tobj::load_mtl_buf(&mut mtl),
+            }
+        },
+    ).map_err(|error| ModelError::Parse(path.to_path_buf(), error))?;
+
+    let materials = match materials {
+        Err(error) => {
+            println!("Materiais de {:?} não carregados: {}", path, error);
+            vec![]
+        },
+        Ok(materials) => materials
+    };
+
+    let mut textures: Vec<TextureSource> = vec![];
+    let materials: Vec<Material> = materials.iter()
+        .map(|material| {
+            let texture = material.diffuse_texture.as_ref().map(|texture| {
+                let texture_path = dir.join(texture);
+                let existing = textures.iter().position(|source| {
+                    matches!(source, TextureSource::File(path) if *path == texture_path)
+                });
+
+                match existing {
+                    Some(index) => index,
+                    None => {
+                        textures.push(TextureSource::File(texture_path));
+                        textures.len() - 1
+                    }
+                }
+            });
+
+            Material::from_mtl(material, texture)
+        })
+        .collect();
+
+    let mut meshes = Vec::with_capacity(models.len());
+    for model in &models {
+        let vertices = load_vertices(&model.name, &model.mesh)?;
+
+        meshes.push(SubMesh {
+            name: model.name.clone(),
+            bounds: Bounds::from_vertices(&vertices),
+            vertices,
+            indices: model.mesh.indices.clone(),
+            material: model.mesh.material_id.filter(|&id| id < materials.len()),
+            index_format: IndexFormat::U32,
+        });
+    }
+
+    let model = Model {
+        meshes,
+        materials,
+        textures,
+    };
+
+    Ok((model, material_files.into_inner()))
+}
+
+fn load_vertices(name: &str, mesh: &tobj::Mesh) -> Result<Vec<Vertex>, ModelError> {
+    if mesh.positions.is_empty() {
+        return Err(ModelError::MissingAttribute {
+            mesh: name.to_string(),
+            attribute: "positions",
+        });
+    }
 
-    let mesh = &models[0].mesh;
     let positions = mesh.positions.as_slice();
+    let colors = mesh.vertex_color.as_slice();
     let coords = mesh.texcoords.as_slice();
+    let normals = mesh.normals.as_slice();
     let vertex_count = mesh.positions.len() / 3;
 
     let mut vertices = Vec::with_capacity(vertex_count);
@@ -38,16 +213,60 @@ pub fn load_model(dir: &str, name: &str) -> (Vec<Vertex>, Vec<u32>) {
         let x = positions[i * 3];
         let y = positions[i * 3 + 1];
         let z = positions[i * 3 + 2];
-        let u = coords[i * 2];
-        let v = coords[i * 2 + 1];
+
+        let color = match colors.get(i * 3..i * 3 + 3) {
+            None => [1.0, 1.0, 1.0],
+            Some(color) => [color[0], color[1], color[2]],
+        };
+
+        let coords = match coords.get(i * 2..i * 2 + 2) {
+            None => [0.0, 0.0],
+            Some(uv) => [uv[0], uv[1]],
+        };
+
+        let normal = match normals.get(i * 3..i * 3 + 3) {
+            None => [0.0, 0.0, 0.0],
+            Some(normal) => [normal[0], normal[1], normal[2]],
+        };
 
         let vertex = Vertex {
             pos: [x, y, z],
-            color: [1.0, 1.0, 1.0],
-            coords: [u, v],
+            color,
+            coords,
+            normal,
         };
         vertices.push(vertex);
     }
 
-    (vertices, mesh.indices.clone())
-} 
\ No newline at end of file
+    if normals.is_empty() {
+        compute_normals(&mut vertices, &mesh.indices);
+    }
+
+    Ok(vertices)
+}
+
+/// Smooth normals for meshes exported without them: every vertex gets the area weighted
+/// average of the faces around it.
+pub fn compute_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut sums = vec![Vector3::new(0.0f32, 0.0, 0.0); vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        let pa = Vector3::from(vertices[a].pos);
+        let pb = Vector3::from(vertices[b].pos);
+        let pc = Vector3::from(vertices[c].pos);
+
+        // not normalized, so bigger faces weigh more
+        let face = (pb - pa).cross(pc - pa);
+        sums[a] += face;
+        sums[b] += face;
+        sums[c] += face;
+    }
+
+    for (vertex, sum) in vertices.iter_mut().zip(sums) {
+        vertex.normal = match sum.magnitude2() > 0.0 {
+            true => sum.normalize().into(),
+            false => [0.0, 1.0, 0.0],
+        };
+    }
+}
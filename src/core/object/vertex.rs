@@ -1,50 +1,23 @@
-use ash::vk;
-use std::mem::size_of;
+use super::layout::vertex_layout;
 
-#[derive(Clone, Copy)]
-pub struct Vertex {
-    pub pos: [f32; 3],
-    pub color: [f32; 3],
-    pub coords: [f32; 2],
+vertex_layout! {
+    #[derive(Clone, Copy, Debug)]
+    pub struct Vertex {
+        pub pos: [f32; 3],
+        pub color: [f32; 3],
+        pub coords: [f32; 2],
+        pub normal: [f32; 3],
+    }
 }
 
 impl Vertex {
-
+    /// The normal defaults to facing +Z, towards a 2D camera.
     pub fn new(pos: [f32; 3], color: [f32; 3], coords: [f32; 2]) -> Self {
         Self {
             pos,
             color,
             coords,
+            normal: [0.0, 0.0, 1.0],
         }
     }
-
-    pub fn get_binding_description() -> vk::VertexInputBindingDescription {
-        vk::VertexInputBindingDescription::builder()
-            .binding(0)
-            .stride(size_of::<Vertex>() as _)
-            .input_rate(vk::VertexInputRate::VERTEX)
-            .build()
-    }
-
-    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
-        let pos = vk::VertexInputAttributeDescription::builder()
-            .binding(0)
-            .location(0)
-            .format(vk::Format::R32G32_SFLOAT)
-            .offset(0)
-            .build();
-        let color = vk::VertexInputAttributeDescription::builder()
-            .binding(0)
-            .location(1)
-            .format(vk::Format::R32G32B32_SFLOAT)
-            .offset(12)
-            .build();
-        let coords_desc = vk::VertexInputAttributeDescription::builder()
-            .binding(0)
-            .location(2)
-            .format(vk::Format::R32G32_SFLOAT)
-            .offset(24)
-            .build();
-        [pos, color, coords_desc]
-    }
 }
\ No newline at end of file
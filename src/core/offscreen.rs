@@ -0,0 +1,230 @@
+use ash::vk;
+
+use gpu_allocator::MemoryLocation;
+
+use crate::core::depth::DepthBuffer;
+use crate::core::device::RendererDevice;
+use crate::core::memory::{AllocatedBuffer, AllocatedImage};
+use crate::core::VulkanRenderer;
+
+use anyhow::Result;
+
+pub struct RendererOffscreen {
+    pub image: AllocatedImage,
+    pub image_view: vk::ImageView,
+    pub framebuffers: Vec<vk::Framebuffer>,
+    pub depth: Option<DepthBuffer>,
+    pub extent: vk::Extent2D,
+    /// Set once a frame was submitted. Until then the image is still `UNDEFINED` and there
+    /// is nothing to read back.
+    pub rendered: bool,
+}
+
+impl RendererOffscreen {
+    /// sRGB like the default `SwapchainConfig` formats, so saved frames match the window.
+    /// RGBA order, as `read_pixels` returns it.
+    pub const FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+    pub fn new(
+        device: &RendererDevice,
+        extent: vk::Extent2D
+    ) -> Result<RendererOffscreen> {
+        println!("Criando alvo offscreen {}x{}...", extent.width, extent.height);
+
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(Self::FORMAT)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let image = AllocatedImage::new(device, "Offscreen color", &image_info, MemoryLocation::GpuOnly)?;
+
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+
+        let image_view_info = vk::ImageViewCreateInfo::builder()
+            .image(image.image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(Self::FORMAT)
+            .subresource_range(*subresource_range);
+
+        let image_view = unsafe {
+            device.logical_device.create_image_view(&image_view_info, None)?
+        };
+
+        Ok(RendererOffscreen {
+            image,
+            image_view,
+            framebuffers: vec![],
+            depth: None,
+            extent,
+            rendered: false,
+        })
+    }
+
+    pub fn create_framebuffers(
+        &mut self,
+        device: &RendererDevice,
+        render_pass: vk::RenderPass,
+        depth_format: vk::Format
+    ) -> Result<()> {
+        let depth = DepthBuffer::new(device, depth_format, self.extent)?;
+        let attachments = [self.image_view, depth.image_view];
+
+        let framebuffer_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass)
+            .attachments(&attachments)
+            .width(self.extent.width)
+            .height(self.extent.height)
+            .layers(1);
+
+        let framebuffer = unsafe {
+            device.logical_device.create_framebuffer(&framebuffer_info, None)?
+        };
+
+        self.framebuffers.push(framebuffer);
+        self.depth = Some(depth);
+
+        Ok(())
+    }
+
+    /// Copies the color image into a host visible buffer and returns it as tightly packed RGBA8 rows.
+    /// The image must already be in `TRANSFER_SRC_OPTIMAL`, which is the final layout of the offscreen render pass,
+    /// so this fails before the first frame was rendered.
+    pub fn read_pixels(
+        &self,
+        device: &RendererDevice,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+    ) -> Result<Vec<u8>> {
+        if !self.rendered {
+            anyhow::bail!("No frame has been rendered yet, draw one before reading pixels");
+        }
+
+        let size = (self.extent.width * self.extent.height * 4) as vk::DeviceSize;
+
+        let mut buffer = AllocatedBuffer::new(
+            device,
+            "Offscreen readback",
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+        )?;
+
+        let copied = VulkanRenderer::execute_one_time_commands(device, command_pool, queue, |command_buffer| {
+            let subresource_range = vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            };
+
+            // make the color writes of previous submissions visible to the copy
+            let image_barriers = [
+                vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                    .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(self.image.image)
+                    .subresource_range(subresource_range)
+                    .build()
+            ];
+
+            let region = vk::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                image_extent: vk::Extent3D {
+                    width: self.extent.width,
+                    height: self.extent.height,
+                    depth: 1,
+                },
+            };
+
+            let buffer_barriers = [
+                vk::BufferMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::HOST_READ)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .buffer(buffer.buffer)
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE)
+                    .build()
+            ];
+
+            unsafe {
+                device.logical_device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &image_barriers,
+                );
+
+                device.logical_device.cmd_copy_image_to_buffer(
+                    command_buffer,
+                    self.image.image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    buffer.buffer,
+                    &[region],
+                );
+
+                device.logical_device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::HOST,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &buffer_barriers,
+                    &[],
+                );
+            };
+        });
+
+        let pixels = copied.and_then(|_| buffer.read());
+        unsafe { buffer.destroy(device) };
+
+        pixels
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        for framebuffer in &self.framebuffers {
+            device.logical_device.destroy_framebuffer(*framebuffer, None);
+        }
+
+        if let Some(depth) = &mut self.depth {
+            depth.cleanup(device);
+        }
+
+        device.logical_device.destroy_image_view(self.image_view, None);
+        self.image.destroy(device);
+    }
+}
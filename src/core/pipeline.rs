@@ -7,26 +7,210 @@ use std::ffi;
 
 use anyhow::Result;
 
+use super::object::layout::VertexLayout;
 use super::object::vertex::Vertex;
-pub struct RendererPipeline {
-    pub pipeline: vk::Pipeline,
-    pub pipeline_layout: vk::PipelineLayout,
+
+#[derive(Clone, Copy, Debug)]
+pub struct DepthState {
+    pub test: bool,
+    pub write: bool,
+    pub compare_op: vk::CompareOp,
 }
 
-impl RendererPipeline {
-    pub fn new(
-        device: &RendererDevice,
-        extent: vk::Extent2D,
-        render_pass: vk::RenderPass
-    ) -> Result<RendererPipeline> {
-        let vert = Shader::from_code_vert(
-            &device.logical_device,
-            vk_shader_macros::include_glsl!("./src/shaders/default.vert")
-        )?;
-        let frag = Shader::from_code_frag(
-            &device.logical_device,
-            vk_shader_macros::include_glsl!("./src/shaders/default.frag")
-        )?;
+impl DepthState {
+    pub fn disabled() -> Self {
+        Self {
+            test: false,
+            write: false,
+            compare_op: vk::CompareOp::ALWAYS,
+        }
+    }
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        Self {
+            test: true,
+            write: true,
+            compare_op: vk::CompareOp::LESS,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Opaque,
+    Alpha,
+    Additive,
+    Multiply,
+    /// For textures whose color was already multiplied by their alpha.
+    Premultiplied,
+}
+
+impl BlendMode {
+    /// In declaration order, see `PipelineHandle::sprite`.
+    pub const ALL: [BlendMode; 5] = [
+        BlendMode::Opaque,
+        BlendMode::Alpha,
+        BlendMode::Additive,
+        BlendMode::Multiply,
+        BlendMode::Premultiplied,
+    ];
+
+    fn attachment_state(self) -> vk::PipelineColorBlendAttachmentState {
+        let (blend_enable, src_color, dst_color, src_alpha, dst_alpha) = match self {
+            BlendMode::Opaque => (
+                false,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ZERO,
+            ),
+            BlendMode::Alpha => (
+                true,
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::Additive => (
+                true,
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ONE,
+            ),
+            BlendMode::Multiply => (
+                true,
+                vk::BlendFactor::DST_COLOR,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ONE,
+            ),
+            BlendMode::Premultiplied => (
+                true,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+        };
+
+        vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(blend_enable)
+            .src_color_blend_factor(src_color)
+            .dst_color_blend_factor(dst_color)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(src_alpha)
+            .dst_alpha_blend_factor(dst_alpha)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()
+    }
+}
+
+pub struct PipelineBuilder<'a> {
+    vertex_shader: Option<&'a [u32]>,
+    fragment_shader: Option<&'a [u32]>,
+    bindings: Vec<vk::VertexInputBindingDescription>,
+    attributes: Vec<vk::VertexInputAttributeDescription>,
+    topology: vk::PrimitiveTopology,
+    cull_mode: vk::CullModeFlags,
+    front_face: vk::FrontFace,
+    blend_mode: BlendMode,
+    depth: DepthState,
+    descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    pub fn new() -> Self {
+        Self {
+            vertex_shader: None,
+            fragment_shader: None,
+            bindings: vec![],
+            attributes: vec![],
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            blend_mode: BlendMode::Alpha,
+            depth: DepthState::default(),
+            descriptor_set_layouts: vec![],
+            push_constant_ranges: vec![],
+        }
+    }
+
+    /// SPIR-V words for the vertex and fragment stages, e.g. from `vk_shader_macros::include_glsl!`.
+    pub fn shaders(mut self, vertex: &'a [u32], fragment: &'a [u32]) -> Self {
+        self.vertex_shader = Some(vertex);
+        self.fragment_shader = Some(fragment);
+        self
+    }
+
+    pub fn vertex_input(
+        mut self,
+        binding: vk::VertexInputBindingDescription,
+        attributes: &[vk::VertexInputAttributeDescription]
+    ) -> Self {
+        self.bindings.push(binding);
+        self.attributes.extend_from_slice(attributes);
+        self
+    }
+
+    /// Adds the next vertex binding with the attributes of `V`, see `vertex_layout!`.
+    pub fn vertex_layout<V: VertexLayout>(self) -> Self {
+        let binding = self.bindings.len() as u32;
+        self.vertex_input(V::binding_description(binding), &V::attribute_descriptions(binding))
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: vk::FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    pub fn depth(mut self, depth: DepthState) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn descriptor_set_layouts(mut self, layouts: &[vk::DescriptorSetLayout]) -> Self {
+        self.descriptor_set_layouts = layouts.to_vec();
+        self
+    }
+
+    pub fn push_constant_range(mut self, range: vk::PushConstantRange) -> Self {
+        self.push_constant_ranges.push(range);
+        self
+    }
+
+    pub fn build(&self, device: &RendererDevice, render_pass: vk::RenderPass) -> Result<RendererPipeline> {
+        let (vertex_code, fragment_code) = match (self.vertex_shader, self.fragment_shader) {
+            (Some(vertex), Some(fragment)) => (vertex, fragment),
+            _ => anyhow::bail!("A pipeline needs both a vertex and a fragment shader"),
+        };
+
+        let vert = Shader::from_code_vert(&device.logical_device, vertex_code)?;
+        let frag = Shader::from_code_frag(&device.logical_device, fragment_code)?;
 
         let entry_point = ffi::CString::new("main").unwrap();
 
@@ -35,26 +219,15 @@ impl RendererPipeline {
             frag.shader_stage(&entry_point),
         ];
 
-        println!("Etapa dos shaders finalizado.");
-
-        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
-
-        println!("Informações de vertice obtidas");
+        let pipeline = self.create_graphics_pipeline(&device.logical_device, render_pass, &shader_stages);
 
-        let (pipeline_layout, pipeline) = Self::create_graphics_pipeline(
-            &device.logical_device,
-            render_pass,
-            extent,
-            vertex_input_info,
-            &shader_stages
-        ).expect("Falha em criar a pipeline de gráficos");
-
-        println!("Pipeline criada!");
         unsafe {
             vert.cleanup(&device.logical_device);
             frag.cleanup(&device.logical_device);
         }
 
+        let (pipeline_layout, pipeline) = pipeline?;
+
         Ok(RendererPipeline {
             pipeline,
             pipeline_layout,
@@ -62,57 +235,38 @@ impl RendererPipeline {
     }
 
     fn create_graphics_pipeline(
+        &self,
         device: &ash::Device,
         render_pass: vk::RenderPass,
-        extent: vk::Extent2D,
-        vertex_input_info: vk::PipelineVertexInputStateCreateInfoBuilder,
-        shader_stages: &[vk::PipelineShaderStageCreateInfo]
+        shader_stages: &[vk::PipelineShaderStageCreateInfo],
     ) -> Result<(vk::PipelineLayout, vk::Pipeline)> {
         // vertex:
-        let binding_descriptions = [Vertex::get_binding_description()];
-        let attribute_descriptions = Vertex::get_attribute_descriptions();
         let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
-            .vertex_binding_descriptions(&binding_descriptions)
-            .vertex_attribute_descriptions(&attribute_descriptions)
+            .vertex_binding_descriptions(&self.bindings)
+            .vertex_attribute_descriptions(&self.attributes)
             .build();
+
         // input:
 
         let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
-
-        // viewport:
-
-        let viewports = [
-            vk::Viewport {
-                x: 0.0,
-                y: 0.0,
-                width: extent.width as f32,
-                height: extent.height as f32,
-                min_depth: 0.0,
-                max_depth: 0.0,
-            }
-        ];
+            .topology(self.topology);
 
-        let scissors = [
-            vk::Rect2D {
-                offset: vk::Offset2D {
-                    x: 0,
-                    y: 0,
-                },
-                extent,
-            }
-        ];
+        // viewport, set per draw through CommandRecorder:
 
         let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
-            .viewports(&viewports)
-            .scissors(&scissors);
+            .viewport_count(1)
+            .scissor_count(1);
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&dynamic_states);
 
         // rasterizer:
 
         let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
             .line_width(1.0)
-            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-            .cull_mode(vk::CullModeFlags::NONE)
+            .front_face(self.front_face)
+            .cull_mode(self.cull_mode)
             .polygon_mode(vk::PolygonMode::FILL);
 
         // multisampler:
@@ -120,37 +274,31 @@ impl RendererPipeline {
         let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
             .rasterization_samples(vk::SampleCountFlags::TYPE_1);
 
+        // depth:
+
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(self.depth.test)
+            .depth_write_enable(self.depth.write)
+            .depth_compare_op(self.depth.compare_op)
+            .depth_bounds_test_enable(false)
+            .stencil_test_enable(false);
+
         // color blend:
 
-        let color_blend_attachments = [
-            vk::PipelineColorBlendAttachmentState::builder()
-                .blend_enable(true)
-                .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-                .color_blend_op(vk::BlendOp::ADD)
-                .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
-                .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-                .alpha_blend_op(vk::BlendOp::ADD)
-                .color_write_mask(
-                    vk::ColorComponentFlags::R
-                        | vk::ColorComponentFlags::G
-                        | vk::ColorComponentFlags::B
-                        | vk::ColorComponentFlags::A,
-                )
-                .build()
-        ];
+        let color_blend_attachments = [self.blend_mode.attachment_state()];
 
         let color_blend_info = vk::PipelineColorBlendStateCreateInfo::builder()
             .attachments(&color_blend_attachments);
 
         // pipeline:
 
-        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder();
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&self.descriptor_set_layouts)
+            .push_constant_ranges(&self.push_constant_ranges);
         let pipeline_layout = unsafe {
             device.create_pipeline_layout(&pipeline_layout_info, None)?
         };
 
-        println!("Pipeline layout criada!");
         let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
             .stages(shader_stages)
             .vertex_input_state(&vertex_input_state)
@@ -158,26 +306,89 @@ impl RendererPipeline {
             .viewport_state(&viewport_info)
             .rasterization_state(&rasterizer_info)
             .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
             .color_blend_state(&color_blend_info)
+            .dynamic_state(&dynamic_state_info)
             .layout(pipeline_layout)
             .render_pass(render_pass)
             .subpass(0)
             .build();
-        
-        println!("Pipeline info criada!");
+
         let pipeline = unsafe {
             device.create_graphics_pipelines(
                 vk::PipelineCache::null(),
                 &[pipeline_info],
                 None,
-            ).unwrap()
-        }[0];
-        println!("Created pipeline {:?}", pipeline);
-        Ok((pipeline_layout, pipeline))
+            )
+        };
+
+        match pipeline {
+            Ok(pipelines) => Ok((pipeline_layout, pipelines[0])),
+            Err((_, error)) => {
+                unsafe { device.destroy_pipeline_layout(pipeline_layout, None) };
+                Err(error.into())
+            }
+        }
+    }
+}
+
+impl<'a> Default for PipelineBuilder<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PipelineBuilder<'static> {
+    /// The default 3D mesh pipeline, drawing `Vertex` data with default.vert/default.frag.
+    pub fn mesh(depth: DepthState) -> Self {
+        PipelineBuilder::new()
+            .shaders(
+                vk_shader_macros::include_glsl!("./src/shaders/default.vert"),
+                vk_shader_macros::include_glsl!("./src/shaders/default.frag"),
+            )
+            .vertex_layout::<Vertex>()
+            .depth(depth)
+    }
+
+    /// The default pipeline with lit.vert/lit.frag, which shade by the vertex normals. Those
+    /// only make sense on closed meshes, so back faces (clockwise on screen, as
+    /// `compute_normals` assumes) are culled.
+    pub fn lit(depth: DepthState) -> Self {
+        PipelineBuilder::new()
+            .shaders(
+                vk_shader_macros::include_glsl!("./src/shaders/lit.vert"),
+                vk_shader_macros::include_glsl!("./src/shaders/lit.frag"),
+            )
+            .vertex_layout::<Vertex>()
+            .cull_mode(vk::CullModeFlags::BACK)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .depth(depth)
     }
 
+    /// Screen space quads from `SpriteBatch`, two triangles each: no depth and no culling, so
+    /// draw order alone decides what ends up on top.
+    pub fn sprite(blend_mode: BlendMode) -> Self {
+        PipelineBuilder::new()
+            .shaders(
+                vk_shader_macros::include_glsl!("./src/shaders/sprite.vert"),
+                vk_shader_macros::include_glsl!("./src/shaders/default.frag"),
+            )
+            .vertex_layout::<Vertex>()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .blend_mode(blend_mode)
+            .depth(DepthState::disabled())
+    }
+}
+
+pub struct RendererPipeline {
+    pub pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+}
+
+impl RendererPipeline {
     pub unsafe fn cleanup(&self, device: &ash::Device) {
         device.destroy_pipeline(self.pipeline, None);
         device.destroy_pipeline_layout(self.pipeline_layout, None);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,139 @@
+use ash::vk;
+
+use crate::core::memory::AllocatedBuffer;
+use crate::core::pipeline::RendererPipeline;
+
+/// A rectangle in framebuffer pixels, used for viewports and scissors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn from_extent(extent: vk::Extent2D) -> Self {
+        Self::new(0.0, 0.0, extent.width as f32, extent.height as f32)
+    }
+}
+
+/// Thin wrapper over a command buffer inside a render pass. Viewport and scissor are dynamic
+/// state, so they can change between draws without building new pipelines.
+pub struct CommandRecorder<'a> {
+    pub device: &'a ash::Device,
+    pub command_buffer: vk::CommandBuffer,
+    pub extent: vk::Extent2D,
+}
+
+impl<'a> CommandRecorder<'a> {
+    pub fn new(device: &'a ash::Device, command_buffer: vk::CommandBuffer, extent: vk::Extent2D) -> Self {
+        Self {
+            device,
+            command_buffer,
+            extent,
+        }
+    }
+
+    pub fn set_viewport(&self, rect: Rect) {
+        let viewports = [
+            vk::Viewport {
+                x: rect.x,
+                y: rect.y,
+                width: rect.width,
+                height: rect.height,
+                min_depth: 0.0,
+                max_depth: 1.0,
+            }
+        ];
+
+        unsafe { self.device.cmd_set_viewport(self.command_buffer, 0, &viewports) };
+    }
+
+    /// Clips drawing to `rect`, clamped to the framebuffer.
+    pub fn set_scissor(&self, rect: Rect) {
+        let x = rect.x.max(0.0).floor();
+        let y = rect.y.max(0.0).floor();
+        let right = (rect.x + rect.width).min(self.extent.width as f32).ceil();
+        let bottom = (rect.y + rect.height).min(self.extent.height as f32).ceil();
+
+        let scissors = [
+            vk::Rect2D {
+                offset: vk::Offset2D {
+                    x: x as i32,
+                    y: y as i32,
+                },
+                extent: vk::Extent2D {
+                    width: (right - x).max(0.0) as u32,
+                    height: (bottom - y).max(0.0) as u32,
+                },
+            }
+        ];
+
+        unsafe { self.device.cmd_set_scissor(self.command_buffer, 0, &scissors) };
+    }
+
+    /// Covers the whole framebuffer with both the viewport and the scissor.
+    pub fn reset_viewport_and_scissor(&self) {
+        let full = Rect::from_extent(self.extent);
+        self.set_viewport(full);
+        self.set_scissor(full);
+    }
+
+    pub fn bind_pipeline(&self, pipeline: &RendererPipeline) {
+        unsafe {
+            self.device.cmd_bind_pipeline(self.command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.pipeline)
+        };
+    }
+
+    pub fn bind_descriptor_set(&self, pipeline: &RendererPipeline, set: vk::DescriptorSet) {
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(
+                self.command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.pipeline_layout,
+                0,
+                &[set],
+                &[],
+            )
+        };
+    }
+
+    /// Pushes `data` at offset 0 of the pipeline's push constant range.
+    pub fn push_constants<T: Copy>(&self, pipeline: &RendererPipeline, stages: vk::ShaderStageFlags, data: &T) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(data as *const T as *const u8, std::mem::size_of::<T>())
+        };
+
+        unsafe {
+            self.device.cmd_push_constants(self.command_buffer, pipeline.pipeline_layout, stages, 0, bytes)
+        };
+    }
+
+    pub fn bind_vertex_buffer(&self, buffer: &AllocatedBuffer) {
+        unsafe { self.device.cmd_bind_vertex_buffers(self.command_buffer, 0, &[buffer.buffer], &[0]) };
+    }
+
+    pub fn bind_index_buffer(&self, buffer: &AllocatedBuffer, index_type: vk::IndexType) {
+        unsafe { self.device.cmd_bind_index_buffer(self.command_buffer, buffer.buffer, 0, index_type) };
+    }
+
+    pub fn draw(&self, vertex_count: u32, first_vertex: u32) {
+        unsafe { self.device.cmd_draw(self.command_buffer, vertex_count, 1, first_vertex, 0) };
+    }
+
+    pub fn draw_indexed(&self, index_count: u32, first_index: u32, vertex_offset: i32) {
+        unsafe {
+            self.device.cmd_draw_indexed(self.command_buffer, index_count, 1, first_index, vertex_offset, 0)
+        };
+    }
+}
@@ -0,0 +1,23 @@
+use crate::core::draw::{MeshHandle, TextureHandle};
+
+/// A material after its texture was uploaded.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderMaterial {
+    pub texture: TextureHandle,
+    pub color: [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RenderPart {
+    pub mesh: MeshHandle,
+    /// Index into `RenderModel::materials`.
+    pub material: Option<usize>,
+}
+
+/// GPU side of an `object::model::Model`, returned by `VulkanRenderer::upload_model`
+/// and drawn with `DrawList::draw_model`.
+#[derive(Clone, Debug)]
+pub struct RenderModel {
+    pub parts: Vec<RenderPart>,
+    pub materials: Vec<RenderMaterial>,
+}
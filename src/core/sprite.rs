@@ -0,0 +1,285 @@
+use ash::vk;
+
+use cgmath::{Matrix4, SquareMatrix};
+use gpu_allocator::MemoryLocation;
+
+use crate::core::camera::OPENGL_TO_VULKAN;
+use crate::core::device::RendererDevice;
+use crate::core::draw::{DrawCommand, DrawList, TextureHandle};
+use crate::core::memory::AllocatedBuffer;
+use crate::core::object::vertex::Vertex;
+use crate::core::recorder::Rect;
+
+use anyhow::Result;
+
+/// A textured quad. `position` is where the `origin` lands, `origin` being a point of the quad
+/// relative to its size (0,0 is the top left corner, 0.5,0.5 the center), which is also the pivot for `rotation`.
+#[derive(Clone, Copy, Debug)]
+pub struct Sprite {
+    pub texture: TextureHandle,
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    pub origin: [f32; 2],
+    /// In radians, clockwise on screen.
+    pub rotation: f32,
+    /// Normalized texture coordinates.
+    pub uv: Rect,
+    /// Multiplies the texture color and alpha.
+    pub tint: [f32; 4],
+    /// Higher layers are drawn on top.
+    pub layer: i32,
+}
+
+impl Sprite {
+    pub fn new(texture: TextureHandle, position: [f32; 2], size: [f32; 2]) -> Self {
+        Self {
+            texture,
+            position,
+            size,
+            origin: [0.0, 0.0],
+            rotation: 0.0,
+            uv: Rect::new(0.0, 0.0, 1.0, 1.0),
+            tint: [1.0, 1.0, 1.0, 1.0],
+            layer: 0,
+        }
+    }
+
+    pub fn origin(mut self, origin: [f32; 2]) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    pub fn rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn tint(mut self, tint: [f32; 4]) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Two triangles, top left / top right / bottom right / bottom left corners. `Vertex` has
+    /// no alpha, so only the tint's color goes in the vertices, see `SpriteBatch::flush`.
+    fn vertices(&self) -> [Vertex; 6] {
+        let (sin, cos) = self.rotation.sin_cos();
+        let [width, height] = self.size;
+        let left = -self.origin[0] * width;
+        let top = -self.origin[1] * height;
+
+        let color = [self.tint[0], self.tint[1], self.tint[2]];
+        let corner = |x: f32, y: f32, u: f32, v: f32| {
+            let pos = [
+                self.position[0] + x * cos - y * sin,
+                self.position[1] + x * sin + y * cos,
+                0.0,
+            ];
+            Vertex::new(pos, color, [u, v])
+        };
+
+        let uv = self.uv;
+        let top_left = corner(left, top, uv.x, uv.y);
+        let top_right = corner(left + width, top, uv.x + uv.width, uv.y);
+        let bottom_right = corner(left + width, top + height, uv.x + uv.width, uv.y + uv.height);
+        let bottom_left = corner(left, top + height, uv.x, uv.y + uv.height);
+
+        [top_left, top_right, bottom_right, bottom_right, bottom_left, top_left]
+    }
+}
+
+/// Collects sprites during a frame and turns them into as few draws as possible.
+/// Sprites are ordered by layer, then grouped by texture inside each layer; sprites of the
+/// same layer and texture keep their submission order. Consecutive sprites sharing a texture
+/// and tint alpha become one draw.
+pub struct SpriteBatch {
+    pub sprites: Vec<Sprite>,
+    /// Maps sprite positions to clip space, see `pixel_projection`.
+    pub transform: Matrix4<f32>,
+}
+
+impl SpriteBatch {
+    pub fn new(transform: Matrix4<f32>) -> Self {
+        Self {
+            sprites: vec![],
+            transform,
+        }
+    }
+
+    /// Pixel coordinates with the origin at the top left corner and y going down.
+    pub fn pixel_projection(width: f32, height: f32) -> Matrix4<f32> {
+        OPENGL_TO_VULKAN * cgmath::ortho(0.0, width, height, 0.0, -1.0, 1.0)
+    }
+
+    pub fn draw(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    /// Appends the batched sprites to `draw_list` and empties the batch. The draws use whatever
+    /// pipeline is bound, so bind one of the `PipelineHandle::sprite` pipelines first.
+    pub fn flush(&mut self, draw_list: &mut DrawList) {
+        self.sprites.sort_by_key(|sprite| (sprite.layer, sprite.texture.0));
+
+        // the tint alpha goes in the push constant color, so it splits runs like the texture does
+        let mut run: Option<(TextureHandle, f32, u32)> = None;
+        for sprite in self.sprites.drain(..) {
+            let first_vertex = draw_list.sprite_vertices.len() as u32;
+            draw_list.sprite_vertices.extend_from_slice(&sprite.vertices());
+
+            let alpha = sprite.tint[3];
+            run = match run {
+                Some((texture, run_alpha, start)) if texture == sprite.texture && run_alpha == alpha => {
+                    Some((texture, run_alpha, start))
+                },
+                Some((texture, run_alpha, start)) => {
+                    Self::push_run(draw_list, texture, run_alpha, start, first_vertex, self.transform);
+                    Some((sprite.texture, alpha, first_vertex))
+                },
+                None => Some((sprite.texture, alpha, first_vertex)),
+            };
+        }
+
+        if let Some((texture, alpha, start)) = run {
+            let end = draw_list.sprite_vertices.len() as u32;
+            Self::push_run(draw_list, texture, alpha, start, end, self.transform);
+        }
+    }
+
+    fn push_run(
+        draw_list: &mut DrawList,
+        texture: TextureHandle,
+        alpha: f32,
+        start: u32,
+        end: u32,
+        transform: Matrix4<f32>,
+    ) {
+        draw_list.commands.push(DrawCommand::DrawSprites {
+            texture,
+            first_vertex: start,
+            vertex_count: end - start,
+            transform,
+            color: [1.0, 1.0, 1.0, alpha],
+        });
+    }
+}
+
+impl Default for SpriteBatch {
+    fn default() -> Self {
+        Self::new(Matrix4::identity())
+    }
+}
+
+/// One host visible vertex buffer per frame in flight holding that frame's sprite vertices.
+/// Buffers grow when a frame has more sprites than fit and are never shrunk.
+pub struct SpriteBuffers {
+    pub buffers: Vec<Option<AllocatedBuffer>>,
+}
+
+impl SpriteBuffers {
+    pub const INITIAL_SPRITES: usize = 1024;
+
+    pub fn new(frames_in_flight: usize) -> Self {
+        Self {
+            buffers: (0..frames_in_flight).map(|_| None).collect(),
+        }
+    }
+
+    /// Writes `vertices` into the buffer of `frame`, which must not be in use by the GPU.
+    pub fn upload(&mut self, device: &RendererDevice, frame: usize, vertices: &[Vertex]) -> Result<()> {
+        if vertices.is_empty() {
+            return Ok(());
+        }
+
+        let size = std::mem::size_of_val(vertices) as vk::DeviceSize;
+        let capacity = match &self.buffers[frame] {
+            Some(buffer) if buffer.size >= size => None,
+            Some(buffer) => Some(size.max(buffer.size * 2)),
+            None => Some(size.max((Self::INITIAL_SPRITES * 6 * std::mem::size_of::<Vertex>()) as vk::DeviceSize)),
+        };
+
+        if let Some(capacity) = capacity {
+            let buffer = AllocatedBuffer::new(
+                device,
+                &format!("Sprite vertices {}", frame),
+                capacity,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                MemoryLocation::CpuToGpu,
+            )?;
+
+            if let Some(mut old) = self.buffers[frame].replace(buffer) {
+                unsafe { old.destroy(device) };
+            }
+        }
+
+        self.buffers[frame].as_mut().unwrap().write(vertices)
+    }
+
+    pub fn get(&self, frame: usize) -> Option<&AllocatedBuffer> {
+        self.buffers[frame].as_ref()
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        for buffer in self.buffers.iter_mut().flatten() {
+            buffer.destroy(device);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (texture, first vertex, vertex count, alpha) of each draw, failing on any other command.
+    fn draws(draw_list: &DrawList) -> Vec<(usize, u32, u32, f32)> {
+        draw_list.commands.iter()
+            .map(|command| match command {
+                DrawCommand::DrawSprites { texture, first_vertex, vertex_count, color, .. } => {
+                    (texture.0, *first_vertex, *vertex_count, color[3])
+                },
+                other => panic!("Unexpected command {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flush_sorts_by_layer_and_texture_and_merges_runs() {
+        let (a, b) = (TextureHandle(1), TextureHandle(2));
+
+        let mut batch = SpriteBatch::default();
+        batch.draw(Sprite::new(b, [0.0, 0.0], [1.0, 1.0]).layer(1));
+        batch.draw(Sprite::new(a, [1.0, 0.0], [1.0, 1.0]).layer(1));
+        batch.draw(Sprite::new(b, [2.0, 0.0], [1.0, 1.0]));
+        batch.draw(Sprite::new(b, [3.0, 0.0], [1.0, 1.0]).layer(1));
+        batch.draw(Sprite::new(a, [4.0, 0.0], [1.0, 1.0]));
+
+        let mut draw_list = DrawList::new();
+        batch.flush(&mut draw_list);
+
+        assert!(batch.sprites.is_empty());
+        assert_eq!(draws(&draw_list), vec![(1, 0, 6, 1.0), (2, 6, 6, 1.0), (1, 12, 6, 1.0), (2, 18, 12, 1.0)]);
+
+        // the first corner of each quad is its position, in draw order
+        let order: Vec<f32> = draw_list.sprite_vertices.chunks_exact(6).map(|quad| quad[0].pos[0]).collect();
+        assert_eq!(order, vec![4.0, 2.0, 1.0, 0.0, 3.0]);
+    }
+
+    #[test]
+    fn flush_splits_runs_on_tint_alpha() {
+        let texture = TextureHandle(1);
+
+        let mut batch = SpriteBatch::default();
+        batch.draw(Sprite::new(texture, [0.0, 0.0], [1.0, 1.0]).tint([1.0, 0.0, 0.0, 1.0]));
+        batch.draw(Sprite::new(texture, [1.0, 0.0], [1.0, 1.0]).tint([0.0, 1.0, 0.0, 1.0]));
+        batch.draw(Sprite::new(texture, [2.0, 0.0], [1.0, 1.0]).tint([1.0, 1.0, 1.0, 0.5]));
+
+        let mut draw_list = DrawList::new();
+        batch.flush(&mut draw_list);
+
+        assert_eq!(draws(&draw_list), vec![(1, 0, 12, 1.0), (1, 12, 6, 0.5)]);
+        assert_eq!(draw_list.sprite_vertices[6].color, [0.0, 1.0, 0.0]);
+    }
+}
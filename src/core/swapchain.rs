@@ -1,105 +1,236 @@
 use ash::vk;
 use ash::extensions::khr;
 
+use crate::core::depth::DepthBuffer;
 use crate::core::device::RendererDevice;
 use crate::core::window::RendererWindow;
 
 use anyhow::Result;
 
+/// How presentation is paced against the display refresh.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VSync {
+    /// Frames are shown as soon as they are done, which may tear.
+    /// Falls back to `Mailbox`, then `On`, when the surface can't do that.
+    Off,
+    /// Waits for the vertical blank. The only mode every surface supports.
+    #[default]
+    On,
+    /// Like `On`, but a late frame is shown right away instead of waiting for the next
+    /// blank, tearing only when the game can't keep up. Falls back to `On`.
+    Adaptive,
+    /// No tearing with the lowest latency, the newest frame replaces any queued one.
+    /// Falls back to `On`.
+    Mailbox,
+}
+
+impl VSync {
+    /// The following mode, wrapping around, to cycle through them at runtime.
+    pub fn next(self) -> Self {
+        match self {
+            VSync::Off => VSync::On,
+            VSync::On => VSync::Adaptive,
+            VSync::Adaptive => VSync::Mailbox,
+            VSync::Mailbox => VSync::Off,
+        }
+    }
+
+    /// Present modes to try, best first. FIFO, supported everywhere, always comes last.
+    fn present_modes(&self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            VSync::Off => &[vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO],
+            VSync::On => &[vk::PresentModeKHR::FIFO],
+            VSync::Adaptive => &[vk::PresentModeKHR::FIFO_RELAXED, vk::PresentModeKHR::FIFO],
+            VSync::Mailbox => &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO],
+        }
+    }
+}
+
+/// What the swapchain should look like, negotiated against what the surface supports whenever
+/// the swapchain is created. Change it at runtime with `VulkanRenderer::set_swapchain_config`.
+#[derive(Clone, Debug)]
+pub struct SwapchainConfig {
+    pub vsync: VSync,
+    /// Tried in order, the first one the surface supports wins, otherwise whatever the surface
+    /// lists first. sRGB formats encode the shader's linear output for the display.
+    pub preferred_formats: Vec<vk::SurfaceFormatKHR>,
+    /// Clamped to the surface's minimum and maximum image counts.
+    pub min_image_count: u32,
+}
+
+impl Default for SwapchainConfig {
+    fn default() -> Self {
+        Self {
+            vsync: VSync::default(),
+            preferred_formats: vec![
+                vk::SurfaceFormatKHR {
+                    format: vk::Format::B8G8R8A8_SRGB,
+                    color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+                },
+                vk::SurfaceFormatKHR {
+                    format: vk::Format::R8G8B8A8_SRGB,
+                    color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+                },
+            ],
+            min_image_count: 3,
+        }
+    }
+}
+
+impl SwapchainConfig {
+    /// `None` only when the surface reports no formats at all.
+    pub fn choose_format(&self, available: &[vk::SurfaceFormatKHR]) -> Option<vk::SurfaceFormatKHR> {
+        // a lone UNDEFINED entry means the surface takes any format
+        if let (Some(preferred), [only]) = (self.preferred_formats.first(), available) {
+            if only.format == vk::Format::UNDEFINED {
+                return Some(*preferred);
+            }
+        }
+
+        self.preferred_formats.iter()
+            .find(|preferred| available.contains(preferred))
+            .or_else(|| available.first())
+            .copied()
+    }
+
+    pub fn choose_present_mode(&self, available: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
+        self.vsync.present_modes().iter()
+            .find(|mode| available.contains(mode))
+            .copied()
+            .unwrap_or(vk::PresentModeKHR::FIFO)
+    }
+
+    pub fn image_count(&self, capabilities: &vk::SurfaceCapabilitiesKHR) -> u32 {
+        let count = self.min_image_count.max(capabilities.min_image_count);
+
+        // a maximum of 0 means there is no limit
+        match capabilities.max_image_count {
+            0 => count,
+            max => count.min(max),
+        }
+    }
+}
+
 pub struct RendererSwapchain {
     pub swapchain_loader: khr::Swapchain,
     pub swapchain: vk::SwapchainKHR,
     pub image_views: Vec<vk::ImageView>,
     pub framebuffers: Vec<vk::Framebuffer>,
+    pub depth: Option<DepthBuffer>,
     pub extent: vk::Extent2D,
-    pub image_available: Vec<vk::Semaphore>,
-    pub rendering_finished: Vec<vk::Semaphore>,
-    pub may_begin_drawing: Vec<vk::Fence>,
     pub image_count: u32,
-    pub current_image: usize,
+    pub format: vk::SurfaceFormatKHR,
+    pub present_mode: vk::PresentModeKHR,
 }
 
 impl RendererSwapchain {
+    /// `format` must be one the surface supports, see `SwapchainConfig::choose_format`. It is
+    /// taken apart from `config` since the render pass is built for it and must keep matching.
+    /// `old_swapchain` is the one being replaced, if any, which is retired by this call either
+    /// way but must still be destroyed by the caller.
     pub fn new(
         instance: &ash::Instance,
         device: &RendererDevice,
-        window: &RendererWindow
+        window: &RendererWindow,
+        format: vk::SurfaceFormatKHR,
+        config: &SwapchainConfig,
+        old_swapchain: vk::SwapchainKHR,
     ) -> Result<RendererSwapchain> {
-
-        let graphics_queue_family = match device.queue_family(vk::QueueFlags::GRAPHICS) {
-            None => panic!("No graphics queue family found, don't know what to do!"),
-            Some(qf) => qf
+        let graphics_family = device.family_indices.graphics;
+        let queue_families = match device.family_indices.present {
+            Some(present_family) if present_family != graphics_family => vec![graphics_family, present_family],
+            _ => vec![graphics_family],
         };
 
-        let queue_families = [graphics_queue_family.index];
-
         let capabilities = window.capabilities(device.physical_device)?;
+        let extent = window.surface_extent(&capabilities);
 
         println!("Criando swapchain...");
-        let formats = window.formats(device.physical_device)?;
-        let format = formats.first().unwrap();
-        println!("Formato: {:?}", format);
+        let present_mode = config.choose_present_mode(&window.present_modes(device.physical_device)?);
+        let min_image_count = config.image_count(&capabilities);
+        println!("Formato: {:?}, modo: {:?}, imagens: {}", format, present_mode, min_image_count);
 
         let (swapchain_loader, swapchain) = Self::create_swapchain(
             window.surface,
             &capabilities,
-            format,
+            extent,
+            &format,
+            present_mode,
+            min_image_count,
             &queue_families,
+            old_swapchain,
             instance,
             device,
-        ).expect("Failed to create swapchain");
+        )?;
 
-        println!("Extent: {:?}", capabilities.current_extent);
+        println!("Extent: {:?}", extent);
 
         let images = unsafe {
-            swapchain_loader.get_swapchain_images(swapchain).expect("Failed to get swapchain images")
+            swapchain_loader.get_swapchain_images(swapchain)
         };
 
-        println!("Swapchain image count: {}", images.len());
-        
-        let image_views = Self::create_image_views(&images, &device)?;
+        let image_views = match images {
+            Ok(images) => Self::create_image_views(&images, format.format, device),
+            Err(error) => Err(error.into()),
+        };
+
+        let image_views = match image_views {
+            Err(error) => {
+                unsafe { swapchain_loader.destroy_swapchain(swapchain, None) };
+                return Err(error);
+            },
+            Ok(image_views) => image_views
+        };
+        println!("Swapchain image count: {}", image_views.len());
 
         let image_count = image_views.len() as u32;
 
-        let mut swapchain = RendererSwapchain {
+        Ok(RendererSwapchain {
             swapchain_loader,
             swapchain,
             image_views,
             framebuffers: vec![],
-            extent: capabilities.current_extent,
-            image_available: vec![],
-            rendering_finished: vec![],
-            may_begin_drawing: vec![],
+            depth: None,
+            extent,
             image_count,
-            current_image: 0,
-        };
-
-        swapchain.create_sync(device)?;
-
-        Ok(swapchain)
+            format,
+            present_mode,
+        })
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn create_swapchain(
         surface: vk::SurfaceKHR,
         capabilities: &vk::SurfaceCapabilitiesKHR,
+        extent: vk::Extent2D,
         format: &vk::SurfaceFormatKHR,
+        present_mode: vk::PresentModeKHR,
+        min_image_count: u32,
         queue_families: &[u32],
+        old_swapchain: vk::SwapchainKHR,
         instance: &ash::Instance,
         device: &RendererDevice,
     ) -> Result<(khr::Swapchain, vk::SwapchainKHR)> {
+        // rendered by the graphics family and presented by another, so let both use the images
+        let sharing_mode = match queue_families.len() {
+            1 => vk::SharingMode::EXCLUSIVE,
+            _ => vk::SharingMode::CONCURRENT,
+        };
+
         let swapchain_info = vk::SwapchainCreateInfoKHR::builder()
             .surface(surface)
-            .min_image_count(3)
+            .min_image_count(min_image_count)
             .image_format(format.format)
             .image_color_space(format.color_space)
-            .image_extent(capabilities.current_extent)
+            .image_extent(extent)
             .image_array_layers(1)
             .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-            .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
-            .queue_family_indices(&queue_families)
+            .image_sharing_mode(sharing_mode)
+            .queue_family_indices(queue_families)
             .pre_transform(capabilities.current_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(vk::PresentModeKHR::IMMEDIATE);
+            .present_mode(present_mode)
+            .old_swapchain(old_swapchain);
 
         let swapchain_loader = khr::Swapchain::new(instance, &device.logical_device);
         let swapchain = unsafe {
@@ -109,7 +240,7 @@ impl RendererSwapchain {
         Ok((swapchain_loader, swapchain))
     }
 
-    fn create_image_views(images: &Vec<vk::Image>, device: &RendererDevice) -> Result<Vec<vk::ImageView>> {
+    fn create_image_views(images: &[vk::Image], format: vk::Format, device: &RendererDevice) -> Result<Vec<vk::ImageView>> {
         let mut image_views = Vec::with_capacity(images.len());
         println!("Tem {} images in the swapchain", images.len());
         for image in images {
@@ -125,54 +256,42 @@ impl RendererSwapchain {
             let image_view_info = vk::ImageViewCreateInfo::builder()
                 .image(*image)
                 .view_type(vk::ImageViewType::TYPE_2D)
-                .format(vk::Format::B8G8R8A8_UNORM)
+                .format(format)
                 .subresource_range(*subresource_range);
 
             let image_view = unsafe {
-                device.logical_device.create_image_view(&image_view_info, None).expect("Failed to create image view")
+                device.logical_device.create_image_view(&image_view_info, None)
             };
 
-            image_views.push(image_view);
+            match image_view {
+                Ok(image_view) => image_views.push(image_view),
+                Err(error) => {
+                    for image_view in image_views {
+                        unsafe { device.logical_device.destroy_image_view(image_view, None) };
+                    }
+                    return Err(error.into());
+                }
+            }
         }
 
         Ok(image_views)
     }
 
-    fn create_sync(&mut self, device: &RendererDevice) -> Result<()> {
-        let semaphore_info = vk::SemaphoreCreateInfo::builder();
-
-        let fence_info = vk::FenceCreateInfo::builder()
-            .flags(vk::FenceCreateFlags::SIGNALED);
-
-        for _ in 0..self.image_count {
-            let semaphore_available = unsafe {
-                device.logical_device.create_semaphore(&semaphore_info, None)?
-            };
-            let semaphore_finished = unsafe {
-                device.logical_device.create_semaphore(&semaphore_info, None)?
-            };
-
-            self.image_available.push(semaphore_available);
-            self.rendering_finished.push(semaphore_finished);
-
-            let fence = unsafe {
-                device.logical_device.create_fence(&fence_info, None)?
-            };
-
-            self.may_begin_drawing.push(fence);
-        }
-
-        Ok(())
-    }
+    pub fn create_framebuffers(
+        &mut self,
+        device: &RendererDevice,
+        render_pass: vk::RenderPass,
+        depth_format: vk::Format
+    ) -> Result<()> {
+        let depth = DepthBuffer::new(device, depth_format, self.extent)?;
 
-    pub fn create_framebuffers(&mut self, device: &RendererDevice, render_pass: vk::RenderPass) -> Result<()> {
         for image_view in &self.image_views {
-            let image_view = [*image_view];
+            let attachments = [*image_view, depth.image_view];
             println!("Creating framebuffer for image view: {:?}", image_view);
 
             let framebuffer_info = vk::FramebufferCreateInfo::builder()
                 .render_pass(render_pass)
-                .attachments(&image_view)
+                .attachments(&attachments)
                 .width(self.extent.width)
                 .height(self.extent.height)
                 .layers(1);
@@ -185,20 +304,14 @@ impl RendererSwapchain {
             self.framebuffers.push(framebuffer);
         }
 
+        self.depth = Some(depth);
+
         Ok(())
     }
 
-    pub unsafe fn cleanup(&self, device: &RendererDevice) {
-        for semaphore in &self.image_available {
-            device.logical_device.destroy_semaphore(*semaphore, None);
-        }
-
-        for semaphore in &self.rendering_finished {
-            device.logical_device.destroy_semaphore(*semaphore, None);
-        }
-
-        for fence in &self.may_begin_drawing {
-            device.logical_device.destroy_fence(*fence, None);
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        if let Some(depth) = &mut self.depth {
+            depth.cleanup(device);
         }
 
         for framebuffer in &self.framebuffers {
@@ -212,3 +325,85 @@ impl RendererSwapchain {
         self.swapchain_loader.destroy_swapchain(self.swapchain, None);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(format: vk::Format) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        }
+    }
+
+    fn capabilities(min_image_count: u32, max_image_count: u32) -> vk::SurfaceCapabilitiesKHR {
+        vk::SurfaceCapabilitiesKHR {
+            min_image_count,
+            max_image_count,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn choose_format_prefers_in_order_then_falls_back() {
+        let config = SwapchainConfig::default();
+
+        let available = [format(vk::Format::R8G8B8A8_UNORM), format(vk::Format::R8G8B8A8_SRGB)];
+        assert_eq!(config.choose_format(&available), Some(format(vk::Format::R8G8B8A8_SRGB)));
+
+        let available = [format(vk::Format::R8G8B8A8_SRGB), format(vk::Format::B8G8R8A8_SRGB)];
+        assert_eq!(config.choose_format(&available), Some(format(vk::Format::B8G8R8A8_SRGB)));
+
+        let available = [format(vk::Format::A2B10G10R10_UNORM_PACK32), format(vk::Format::R8G8B8A8_UNORM)];
+        assert_eq!(config.choose_format(&available), Some(format(vk::Format::A2B10G10R10_UNORM_PACK32)));
+
+        assert_eq!(config.choose_format(&[format(vk::Format::UNDEFINED)]), Some(format(vk::Format::B8G8R8A8_SRGB)));
+        assert_eq!(config.choose_format(&[]), None);
+    }
+
+    #[test]
+    fn choose_present_mode_falls_back_to_fifo() {
+        let mut config = SwapchainConfig::default();
+        let only_fifo = [vk::PresentModeKHR::FIFO];
+        let all = [
+            vk::PresentModeKHR::FIFO,
+            vk::PresentModeKHR::FIFO_RELAXED,
+            vk::PresentModeKHR::MAILBOX,
+            vk::PresentModeKHR::IMMEDIATE,
+        ];
+
+        config.vsync = VSync::Off;
+        assert_eq!(config.choose_present_mode(&all), vk::PresentModeKHR::IMMEDIATE);
+        assert_eq!(config.choose_present_mode(&[vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX]), vk::PresentModeKHR::MAILBOX);
+        assert_eq!(config.choose_present_mode(&only_fifo), vk::PresentModeKHR::FIFO);
+
+        config.vsync = VSync::Adaptive;
+        assert_eq!(config.choose_present_mode(&all), vk::PresentModeKHR::FIFO_RELAXED);
+        assert_eq!(config.choose_present_mode(&only_fifo), vk::PresentModeKHR::FIFO);
+
+        config.vsync = VSync::Mailbox;
+        assert_eq!(config.choose_present_mode(&all), vk::PresentModeKHR::MAILBOX);
+        assert_eq!(config.choose_present_mode(&only_fifo), vk::PresentModeKHR::FIFO);
+
+        config.vsync = VSync::On;
+        assert_eq!(config.choose_present_mode(&all), vk::PresentModeKHR::FIFO);
+        // even a surface reporting nothing gets FIFO, which the spec guarantees
+        assert_eq!(config.choose_present_mode(&[]), vk::PresentModeKHR::FIFO);
+    }
+
+    #[test]
+    fn image_count_is_clamped_to_the_surface() {
+        let config = SwapchainConfig {
+            min_image_count: 3,
+            ..Default::default()
+        };
+
+        assert_eq!(config.image_count(&capabilities(2, 8)), 3);
+        assert_eq!(config.image_count(&capabilities(4, 8)), 4);
+        assert_eq!(config.image_count(&capabilities(1, 2)), 2);
+        // no maximum
+        assert_eq!(config.image_count(&capabilities(1, 0)), 3);
+        assert_eq!(config.image_count(&capabilities(5, 0)), 5);
+    }
+}
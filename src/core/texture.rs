@@ -0,0 +1,448 @@
+use ash::vk;
+
+use gpu_allocator::MemoryLocation;
+
+use crate::core::capabilities::DeviceFeature;
+use crate::core::commandpool::CommandPools;
+use crate::core::device::RendererDevice;
+use crate::core::memory::{AllocatedBuffer, AllocatedImage};
+use crate::core::object::model::resolve_path;
+use crate::core::VulkanRenderer;
+
+use std::path::Path;
+
+use anyhow::Result;
+
+pub struct Texture {
+    pub image: AllocatedImage,
+    pub image_view: vk::ImageView,
+    pub sampler: vk::Sampler,
+}
+
+impl Texture {
+    pub const FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+    /// Decodes a PNG or JPEG file, looked up like models by `object::model::resolve_path`.
+    pub fn from_file<P: AsRef<Path>>(
+        instance: &ash::Instance,
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        path: P,
+    ) -> Result<Texture> {
+        let fullpath = match resolve_path(&path) {
+            None => anyhow::bail!("Texture {:?} not found", path.as_ref()),
+            Some(fullpath) => fullpath
+        };
+        println!("Carregando textura {:?}", fullpath);
+
+        let image = image::open(&fullpath)?.to_rgba8();
+        let (width, height) = image.dimensions();
+
+        Self::from_rgba(
+            instance,
+            device,
+            command_pools,
+            &fullpath.to_string_lossy(),
+            width,
+            height,
+            image.as_raw(),
+        )
+    }
+
+    /// 1x1 opaque white texture, bound when nothing else was provided so sampling is a no-op.
+    pub fn white(
+        instance: &ash::Instance,
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+    ) -> Result<Texture> {
+        Self::from_rgba(instance, device, command_pools, "White texture", 1, 1, &[255, 255, 255, 255])
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_rgba(
+        instance: &ash::Instance,
+        device: &RendererDevice,
+        command_pools: &CommandPools,
+        name: &str,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<Texture> {
+        if pixels.len() != (width * height * 4) as usize {
+            anyhow::bail!("Expected {} RGBA bytes for a {}x{} texture, got {}", width * height * 4, width, height, pixels.len());
+        }
+
+        let extent = vk::Extent2D { width, height };
+        let mip_levels = Self::mip_levels_for(extent);
+
+        let format_properties = unsafe {
+            instance.get_physical_device_format_properties(device.physical_device, Self::FORMAT)
+        };
+        let can_blit = format_properties.optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR);
+        let mip_levels = if can_blit { mip_levels } else { 1 };
+
+        let mut staging_buffer = AllocatedBuffer::new(
+            device,
+            &format!("{} (staging)", name),
+            pixels.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        )?;
+        if let Err(error) = staging_buffer.write(pixels) {
+            unsafe { staging_buffer.destroy(device) };
+            return Err(error);
+        }
+
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(Self::FORMAT)
+            .extent(vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            })
+            .mip_levels(mip_levels)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let mut image = match AllocatedImage::new(device, name, &image_info, MemoryLocation::GpuOnly) {
+            Err(error) => {
+                unsafe { staging_buffer.destroy(device) };
+                return Err(error);
+            },
+            Ok(image) => image
+        };
+
+        let separate = device.has_separate_transfer();
+
+        // the copy runs on the transfer queue, but blitting the mip chain needs graphics
+        let uploaded = VulkanRenderer::execute_one_time_commands(device, command_pools.transfer, device.transfer_queue, |command_buffer| {
+            Self::transition_layout(
+                device,
+                command_buffer,
+                image.image,
+                0,
+                mip_levels,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            );
+
+            let region = vk::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                image_extent: vk::Extent3D {
+                    width,
+                    height,
+                    depth: 1,
+                },
+            };
+
+            unsafe {
+                device.logical_device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    staging_buffer.buffer,
+                    image.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region],
+                );
+            };
+
+            match separate {
+                true => Self::transfer_ownership(device, command_buffer, image.image, mip_levels, true),
+                false => Self::generate_mipmaps(device, command_buffer, image.image, extent, mip_levels),
+            }
+        }).and_then(|_| match separate {
+            false => Ok(()),
+            true => VulkanRenderer::execute_one_time_commands(device, command_pools.graphics, device.graphics_queue, |command_buffer| {
+                Self::transfer_ownership(device, command_buffer, image.image, mip_levels, false);
+                Self::generate_mipmaps(device, command_buffer, image.image, extent, mip_levels);
+            }),
+        });
+
+        unsafe { staging_buffer.destroy(device) };
+
+        if let Err(error) = uploaded {
+            unsafe { image.destroy(device) };
+            return Err(error);
+        }
+
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(mip_levels)
+            .base_array_layer(0)
+            .layer_count(1);
+
+        let image_view_info = vk::ImageViewCreateInfo::builder()
+            .image(image.image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(Self::FORMAT)
+            .subresource_range(*subresource_range);
+
+        let image_view = unsafe {
+            device.logical_device.create_image_view(&image_view_info, None)?
+        };
+
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::REPEAT)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .anisotropy_enable(device.capabilities.has(DeviceFeature::SamplerAnisotropy))
+            .max_anisotropy(device.capabilities.max_anisotropy())
+            .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+            .unnormalized_coordinates(false)
+            .compare_enable(false)
+            .compare_op(vk::CompareOp::ALWAYS)
+            .min_lod(0.0)
+            .max_lod(mip_levels as f32)
+            .mip_lod_bias(0.0);
+
+        let sampler = unsafe {
+            device.logical_device.create_sampler(&sampler_info, None)?
+        };
+
+        Ok(Texture {
+            image,
+            image_view,
+            sampler,
+        })
+    }
+
+    fn mip_levels_for(extent: vk::Extent2D) -> u32 {
+        32 - extent.width.max(extent.height).leading_zeros()
+    }
+
+    fn transition_layout(
+        device: &RendererDevice,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        base_mip_level: u32,
+        level_count: u32,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+    ) {
+        let (src_access_mask, dst_access_mask, src_stage, dst_stage) = match (old_layout, new_layout) {
+            (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) => (
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+            ),
+            (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL) => (
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+            ),
+            (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => (
+                vk::AccessFlags::TRANSFER_READ,
+                vk::AccessFlags::SHADER_READ,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+            ),
+            (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => (
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+            ),
+            _ => panic!("Unsupported layout transition {:?} -> {:?}", old_layout, new_layout),
+        };
+
+        let barriers = [
+            vk::ImageMemoryBarrier::builder()
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level,
+                    level_count,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .build()
+        ];
+
+        unsafe {
+            device.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &barriers,
+            );
+        };
+    }
+
+    /// Moves the whole image, still in `TRANSFER_DST_OPTIMAL`, from the transfer to the graphics
+    /// family. Recorded twice: the `release` half on the transfer queue, then the acquire half
+    /// on the graphics queue.
+    fn transfer_ownership(
+        device: &RendererDevice,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        mip_levels: u32,
+        release: bool,
+    ) {
+        let (src_access_mask, dst_access_mask, src_stage, dst_stage) = match release {
+            true => (
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::empty(),
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            ),
+            false => (
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+            ),
+        };
+
+        let barriers = [
+            vk::ImageMemoryBarrier::builder()
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .src_queue_family_index(device.family_indices.transfer)
+                .dst_queue_family_index(device.family_indices.graphics)
+                .image(image)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: mip_levels,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .build()
+        ];
+
+        unsafe {
+            device.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &barriers,
+            );
+        };
+    }
+
+    /// Blits every level from the previous one, leaving the whole chain in `SHADER_READ_ONLY_OPTIMAL`.
+    fn generate_mipmaps(
+        device: &RendererDevice,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        extent: vk::Extent2D,
+        mip_levels: u32,
+    ) {
+        let mut mip_width = extent.width as i32;
+        let mut mip_height = extent.height as i32;
+
+        for level in 1..mip_levels {
+            Self::transition_layout(
+                device,
+                command_buffer,
+                image,
+                level - 1,
+                1,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            );
+
+            let next_width = (mip_width / 2).max(1);
+            let next_height = (mip_height / 2).max(1);
+
+            let blit = vk::ImageBlit {
+                src_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level - 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                src_offsets: [
+                    vk::Offset3D { x: 0, y: 0, z: 0 },
+                    vk::Offset3D { x: mip_width, y: mip_height, z: 1 },
+                ],
+                dst_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                dst_offsets: [
+                    vk::Offset3D { x: 0, y: 0, z: 0 },
+                    vk::Offset3D { x: next_width, y: next_height, z: 1 },
+                ],
+            };
+
+            unsafe {
+                device.logical_device.cmd_blit_image(
+                    command_buffer,
+                    image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[blit],
+                    vk::Filter::LINEAR,
+                );
+            };
+
+            Self::transition_layout(
+                device,
+                command_buffer,
+                image,
+                level - 1,
+                1,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+
+            mip_width = next_width;
+            mip_height = next_height;
+        }
+
+        // the last level was only ever written to
+        Self::transition_layout(
+            device,
+            command_buffer,
+            image,
+            mip_levels - 1,
+            1,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        device.logical_device.destroy_sampler(self.sampler, None);
+        device.logical_device.destroy_image_view(self.image_view, None);
+        self.image.destroy(device);
+    }
+}
@@ -0,0 +1,78 @@
+use ash::vk;
+
+use cgmath::{Matrix4, SquareMatrix};
+use gpu_allocator::MemoryLocation;
+
+use crate::core::device::RendererDevice;
+use crate::core::light::LightUniform;
+use crate::core::memory::AllocatedBuffer;
+
+use anyhow::Result;
+
+/// Mirrors `UniformBufferObject` at binding 0 of default.vert. The model matrix is per draw,
+/// in the push constants.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct UniformBufferObject {
+    pub view: Matrix4<f32>,
+    pub proj: Matrix4<f32>,
+}
+
+impl Default for UniformBufferObject {
+    fn default() -> Self {
+        Self {
+            view: Matrix4::identity(),
+            proj: Matrix4::identity(),
+        }
+    }
+}
+
+/// One host visible uniform buffer per slot, so a slot can be rewritten while the GPU still reads the others.
+/// `lights` holds the `LightUniform` of each slot.
+pub struct UniformBuffers {
+    pub buffers: Vec<AllocatedBuffer>,
+    pub lights: Vec<AllocatedBuffer>,
+}
+
+impl UniformBuffers {
+    pub fn new(device: &RendererDevice, count: usize) -> Result<UniformBuffers> {
+        let size = std::mem::size_of::<UniformBufferObject>() as vk::DeviceSize;
+        let light_size = std::mem::size_of::<LightUniform>() as vk::DeviceSize;
+        let mut buffers = Vec::with_capacity(count);
+        let mut lights = Vec::with_capacity(count);
+
+        for i in 0..count {
+            buffers.push(AllocatedBuffer::new(
+                device,
+                &format!("Uniform buffer {}", i),
+                size,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                MemoryLocation::CpuToGpu,
+            )?);
+
+            lights.push(AllocatedBuffer::new(
+                device,
+                &format!("Light uniform buffer {}", i),
+                light_size,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                MemoryLocation::CpuToGpu,
+            )?);
+        }
+
+        Ok(UniformBuffers { buffers, lights })
+    }
+
+    pub fn update(&mut self, index: usize, ubo: &UniformBufferObject) -> Result<()> {
+        self.buffers[index].write(std::slice::from_ref(ubo))
+    }
+
+    pub fn update_light(&mut self, index: usize, light: &LightUniform) -> Result<()> {
+        self.lights[index].write(std::slice::from_ref(light))
+    }
+
+    pub unsafe fn cleanup(&mut self, device: &RendererDevice) {
+        for buffer in self.buffers.iter_mut().chain(self.lights.iter_mut()) {
+            buffer.destroy(device);
+        }
+    }
+}
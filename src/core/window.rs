@@ -50,6 +50,35 @@ impl RendererWindow {
         self.surface_loader.destroy_surface(self.surface, None);
     }
 
+    /// A minimized window reports a zero sized surface, which can't back a swapchain.
+    pub fn is_minimized(&self) -> bool {
+        let size = self.window.inner_size();
+        size.width == 0 || size.height == 0
+    }
+
+    /// Size the swapchain images should have. Surfaces that leave it to the swapchain (Wayland,
+    /// some X11 setups) report `u32::MAX` as current extent, then the window's size in physical
+    /// pixels is used, clamped to what the surface accepts.
+    pub fn surface_extent(&self, capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::Extent2D {
+        if capabilities.current_extent.width != u32::MAX {
+            return capabilities.current_extent;
+        }
+
+        let size = self.window.inner_size();
+        let min = capabilities.min_image_extent;
+        let max = capabilities.max_image_extent;
+
+        vk::Extent2D {
+            width: size.width.clamp(min.width, max.width.max(min.width)),
+            height: size.height.clamp(min.height, max.height.max(min.height)),
+        }
+    }
+
+    /// Physical pixels per logical pixel of the monitor the window is on, e.g. 2.0 on most HiDPI screens.
+    pub fn scale_factor(&self) -> f64 {
+        self.window.scale_factor()
+    }
+
     pub fn acquire_event_loop(&mut self) -> Result<EventLoop<()>> {
         match self.event_loop.take() {
             None => anyhow::bail!("EventLoop was acquired before"),
@@ -74,4 +103,13 @@ impl RendererWindow {
             self.surface_loader.get_physical_device_surface_formats(physical_device, self.surface)
         }
     }
-}
\ No newline at end of file
+
+    pub fn present_modes(
+        &self,
+        physical_device: vk::PhysicalDevice
+    ) -> Result<Vec<vk::PresentModeKHR>, vk::Result> {
+        unsafe {
+            self.surface_loader.get_physical_device_surface_present_modes(physical_device, self.surface)
+        }
+    }
+}
@@ -1,18 +1,115 @@
 mod core;
 use ash::vk;
-use winit::event::{Event, WindowEvent};
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use crate::core::VulkanRenderer;
+use crate::core::camera2d::{Camera2D, ScalePolicy};
+use crate::core::draw::{DrawList, PipelineHandle, TextureHandle};
+use crate::core::object::optimize::OptimizeOptions;
+use crate::core::rendermodel::RenderModel;
+use crate::core::sprite::{Sprite, SpriteBatch};
 use anyhow::Result;
+use cgmath::{Matrix4, Rad, SquareMatrix};
 
+fn load_duck(renderer: &mut VulkanRenderer) -> Result<RenderModel> {
+    let model = core::object::model::load_model("models/duck.obj", &OptimizeOptions::default())?;
+    renderer.upload_model(&model)
+}
+
+/// The 2D overlay's text box, in design coordinates: x, y, width, height.
+const TEXT_BOX: [f32; 4] = [16.0, 794.0, 1888.0, 270.0];
+
+fn overlay_camera() -> Camera2D {
+    Camera2D::new(1920.0, 1080.0, ScalePolicy::Fit)
+}
+
+fn in_text_box([x, y]: [f32; 2]) -> bool {
+    let [left, top, width, height] = TEXT_BOX;
+    (left..left + width).contains(&x) && (top..top + height).contains(&y)
+}
+
+/// A translucent text box over the 3D scene, more opaque while `hovered`, with a spinning
+/// "continue" marker in its corner.
+fn draw_text_box(draw_list: &mut DrawList, camera: &Camera2D, extent: vk::Extent2D, time: f32, hovered: bool) {
+    let [left, top, width, height] = TEXT_BOX;
+    let alpha = match hovered {
+        true => 0.8,
+        false => 0.6,
+    };
+
+    let mut sprites = SpriteBatch::new(camera.projection());
+    sprites.draw(
+        Sprite::new(TextureHandle::WHITE, [left, top], [width, height])
+            .tint([0.0, 0.0, 0.0, alpha])
+    );
+    sprites.draw(
+        Sprite::new(TextureHandle::WHITE, [left + width - 32.0, top + height - 32.0], [16.0, 16.0])
+            .origin([0.5, 0.5])
+            .rotation(time * 2.0)
+            .layer(1)
+    );
+
+    camera.apply(extent, draw_list);
+    draw_list.set_pipeline(PipelineHandle::SPRITE);
+    sprites.flush(draw_list);
+    draw_list.reset_viewport();
+}
+
+/// A small square in the top right corner of the window, outside the design canvas. It is laid
+/// out in logical pixels, so it keeps its size on HiDPI screens.
+fn draw_corner_marker(draw_list: &mut DrawList, [width, height]: [f32; 2]) {
+    let mut sprites = SpriteBatch::new(SpriteBatch::pixel_projection(width, height));
+    sprites.draw(
+        Sprite::new(TextureHandle::WHITE, [width - 8.0, 8.0], [12.0, 12.0])
+            .origin([1.0, 0.0])
+            .tint([1.0, 0.8, 0.2, 1.0])
+    );
+
+    draw_list.set_pipeline(PipelineHandle::SPRITE);
+    sprites.flush(draw_list);
+}
+
+fn run_headless(output: &str) -> Result<()> {
+    let mut renderer = VulkanRenderer::new_headless(800, 600)?;
+    let duck = load_duck(&mut renderer)?;
+
+    let extent = renderer.extent();
+
+    let mut draw_list = DrawList::new();
+    draw_list.set_pipeline(PipelineHandle::LIT);
+    draw_list.draw_model(&duck, Matrix4::identity());
+    draw_text_box(&mut draw_list, &overlay_camera(), extent, 0.0, false);
+    draw_corner_marker(&mut draw_list, renderer.logical_size());
+    renderer.draw_frame(&draw_list)?;
+
+    let pixels = renderer.read_pixels()?;
+
+    // binary PPM, dropping the alpha channel
+    let mut ppm = format!("P6\n{} {}\n255\n", extent.width, extent.height).into_bytes();
+    for pixel in pixels.chunks_exact(4) {
+        ppm.extend_from_slice(&pixel[..3]);
+    }
+    std::fs::write(output, ppm)?;
+
+    println!("Frame salvo em {}", output);
+    Ok(())
+}
 
 fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() > 1 && args[1] == "--headless" {
+        let output = args.get(2).map(|s| s.as_str()).unwrap_or("frame.ppm");
+        return run_headless(output);
+    }
+
     let mut renderer = VulkanRenderer::new()?;
-    let event_loop = renderer.window.acquire_event_loop()?;
+    let event_loop = renderer.acquire_event_loop()?;
 
-    let graphics_queue = match renderer.main_device.queue_family(vk::QueueFlags::GRAPHICS) {
-        None => panic!("No graphics queue found"),
-        Some(qf) => qf.queues[0]
-    };
+    let duck = load_duck(&mut renderer)?;
+    let mut draw_list = DrawList::new();
+    let start = std::time::Instant::now();
+
+    let mut camera = overlay_camera();
+    let mut hovered = false;
 
     event_loop.run(move |event, _, control_flow| {
         match event {
@@ -22,80 +119,82 @@ fn main() -> Result<()> {
             } => {
                 *control_flow = winit::event_loop::ControlFlow::Exit;
             },
+            Event::WindowEvent {
+                event: WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. },
+                ..
+            } => {
+                renderer.handle_resize();
+            },
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                ..
+            } => {
+                let cursor = camera.screen_to_design(renderer.extent(), position.x as f32, position.y as f32);
+                hovered = cursor.is_some_and(in_text_box);
+            },
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput {
+                    input: KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Tab),
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } => {
+                camera.policy = camera.policy.next();
+                println!("Escala do overlay: {:?}", camera.policy);
+            },
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput {
+                    input: KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::V),
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } => {
+                let mut config = renderer.swapchain_config.clone();
+                config.vsync = config.vsync.next();
+                println!("VSync {:?}, swapchain atual: {:?}", config.vsync, renderer.swapchain_mode());
+                renderer.set_swapchain_config(config);
+            },
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput {
+                    input: KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::F),
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } => {
+                let frames_in_flight = match renderer.frames.frames_in_flight() {
+                    2 => 3,
+                    _ => 2,
+                };
+                renderer.set_frames_in_flight(frames_in_flight).expect("Falha ao trocar os frames em voo");
+                println!("Frames em voo: {}", frames_in_flight);
+            },
+            Event::MainEventsCleared => {
+                // keep drawing continuously, but stay paused while minimized
+                renderer.request_redraw();
+            },
             Event::RedrawRequested(_) => {
-                // acquiring next image:
-                if renderer.swapchain.image_count > 0 {
-                    renderer.swapchain.current_image = (renderer.swapchain.current_image + 1) % renderer.swapchain.image_count as usize;
-                } else {
-                    // Handle the case where image_count is zero.
-                    // For example, you could choose a default value or log an error message.
-                    println!("image_count is zero")
-                }
-                
-                if renderer.swapchain.image_available.len() > 0 {
-                    let (image_index, _) = unsafe {
-                        renderer.swapchain.swapchain_loader.acquire_next_image(
-                            renderer.swapchain.swapchain,
-                            u64::MAX,
-                            renderer.swapchain.image_available[renderer.swapchain.current_image],
-                            vk::Fence::null(),
-                        ).unwrap()
-                    };
-                    // fences:
-                    unsafe {
-                        let fences = [renderer.swapchain.may_begin_drawing[renderer.swapchain.current_image]];
-    
-                        renderer.main_device.logical_device.wait_for_fences(
-                            &fences,
-                            true,
-                            u64::MAX,
-                        ).unwrap();
-    
-                        renderer.main_device.logical_device.reset_fences(
-                            &fences,
-                        ).unwrap();
-                    };
-    
-                    // submit:
-                    let semaphores_available = [renderer.swapchain.image_available[renderer.swapchain.current_image]];
-                    let waiting_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-                    let semaphores_finished = [renderer.swapchain.rendering_finished[renderer.swapchain.current_image]];
-                    let command_buffers = [renderer.graphics_command_buffers[image_index as usize]];
-    
-                    let submit_info = [
-                        vk::SubmitInfo::builder()
-                            .wait_semaphores(&semaphores_available)
-                            .wait_dst_stage_mask(&waiting_stages)
-                            .command_buffers(&command_buffers)
-                            .signal_semaphores(&semaphores_finished)
-                            .build()
-                    ];
-    
-                    unsafe {
-                        renderer.main_device.logical_device.queue_submit(
-                            graphics_queue,
-                            &submit_info,
-                            renderer.swapchain.may_begin_drawing[renderer.swapchain.current_image],
-                        ).unwrap();
-                    };
-    
-                    // present:
-                    let swapchains = [renderer.swapchain.swapchain];
-                    let indices = [image_index];
-    
-                    let present_info = vk::PresentInfoKHR::builder()
-                        .wait_semaphores(&semaphores_finished)
-                        .swapchains(&swapchains)
-                        .image_indices(&indices);
-    
-                    unsafe {
-                        renderer.swapchain.swapchain_loader
-                            .queue_present(graphics_queue, &present_info)
-                            .unwrap();
-                    };
-                }
+                let time = start.elapsed().as_secs_f32();
+
+                draw_list.clear();
+                draw_list.set_pipeline(PipelineHandle::LIT);
+                draw_list.draw_model(&duck, Matrix4::from_angle_y(Rad(time)));
+                draw_text_box(&mut draw_list, &camera, renderer.extent(), time, hovered);
+                draw_corner_marker(&mut draw_list, renderer.logical_size());
+                renderer.draw_frame(&draw_list).expect("Falha ao desenhar o frame");
             },
             _ => {}
         }
     });
-}
\ No newline at end of file
+}
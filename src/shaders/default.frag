@@ -6,8 +6,13 @@ layout(location = 1) in vec2 fragCoords;
 
 layout(binding = 1) uniform sampler2D texSampler;
 
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+} push;
+
 layout(location = 0) out vec4 outColor;
 
 void main() {
-    outColor = texture(texSampler, fragCoords)*vec4(fragColor, 1.0);
+    outColor = texture(texSampler, fragCoords)*vec4(fragColor, 1.0)*push.color;
 }
\ No newline at end of file
@@ -6,16 +6,20 @@ layout(location = 1) in vec3 vColor;
 layout(location = 2) in vec2 vCoords;
 
 layout(binding = 0) uniform UniformBufferObject {
-    mat4 model;
     mat4 view;
     mat4 proj;
 } ubo;
 
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+} push;
+
 layout(location = 0) out vec3 fragColor;
 layout(location = 1) out vec2 fragCoords;
 
 void main() {
-    gl_Position = ubo.proj * ubo.view * ubo.model * vec4(vPosition, 1.0);
+    gl_Position = ubo.proj * ubo.view * push.model * vec4(vPosition, 1.0);
     fragColor = vColor;
     fragCoords = vCoords;
 }
\ No newline at end of file
@@ -0,0 +1,28 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec2 fragCoords;
+layout(location = 2) in vec3 fragNormal;
+
+layout(binding = 1) uniform sampler2D texSampler;
+
+layout(binding = 2) uniform Light {
+    vec4 direction;
+    vec4 color;
+    vec4 ambient;
+} light;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+} push;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec4 base = texture(texSampler, fragCoords)*vec4(fragColor, 1.0)*push.color;
+    float diffuse = max(dot(normalize(fragNormal), -light.direction.xyz), 0.0);
+
+    outColor = vec4(base.rgb*(light.ambient.rgb + light.color.rgb*diffuse), base.a);
+}
\ No newline at end of file
@@ -0,0 +1,28 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 vPosition;
+layout(location = 1) in vec3 vColor;
+layout(location = 2) in vec2 vCoords;
+layout(location = 3) in vec3 vNormal;
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+} push;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragCoords;
+layout(location = 2) out vec3 fragNormal;
+
+void main() {
+    gl_Position = ubo.proj * ubo.view * push.model * vec4(vPosition, 1.0);
+    fragColor = vColor;
+    fragCoords = vCoords;
+    fragNormal = mat3(transpose(inverse(push.model))) * vNormal;
+}
\ No newline at end of file
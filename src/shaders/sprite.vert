@@ -0,0 +1,20 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 vPosition;
+layout(location = 1) in vec3 vColor;
+layout(location = 2) in vec2 vCoords;
+
+layout(push_constant) uniform PushConstants {
+    mat4 transform;
+    vec4 color;
+} push;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragCoords;
+
+void main() {
+    gl_Position = push.transform * vec4(vPosition, 1.0);
+    fragColor = vColor;
+    fragCoords = vCoords;
+}
\ No newline at end of file